@@ -0,0 +1,134 @@
+/// Motor, gear and friction of a joint.
+///
+/// Torques without a prefix are on the joint side of the gear. The joint
+/// torque of the dynamics in `Rkd` includes `armature() * ddq + friction(dq)`.
+/// The loss of `efficiency` depends on the power flow, so it is left to
+/// `motor_torque` and `joint_torque`, applied to every joint by
+/// `Rkd::motor_torques` and `Rkd::joint_torques`.
+#[derive(Debug, Clone)]
+pub struct Actuator<T> {
+    pub gear_ratio: T,        // motor turns per joint turn
+    pub rotor_inertia: T,     // on the motor side
+    pub efficiency: T,        // of the gear, in (0, 1]
+    pub coulomb: T,           // kinetic friction torque
+    pub static_friction: T,   // breakaway friction torque
+    pub stribeck_velocity: T, // speed over which static friction fades to coulomb
+    pub viscous: T,           // friction torque per joint speed
+}
+
+impl<T: na::RealField> Default for Actuator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Actuator<T> {
+    pub fn new() -> Self {
+        Self {
+            gear_ratio: T::one(),
+            rotor_inertia: T::zero(),
+            efficiency: T::one(),
+            coulomb: T::zero(),
+            static_friction: T::zero(),
+            stribeck_velocity: T::zero(),
+            viscous: T::zero(),
+        }
+    }
+
+    /// Rotor inertia reflected to the joint, `gear_ratio² × rotor_inertia`.
+    pub fn armature(&self) -> T {
+        self.gear_ratio.clone() * self.gear_ratio.clone() * self.rotor_inertia.clone()
+    }
+
+    /// Friction torque at the joint speed `dq`, opposing the motion.
+    ///
+    /// The Coulomb part rises to `static_friction` near rest with the
+    /// Stribeck curve `exp(-(dq / stribeck_velocity)²)`, which is left out
+    /// when `stribeck_velocity` is zero. At rest the friction is zero.
+    pub fn friction(&self, dq: T) -> T {
+        let sign = if dq > T::zero() {
+            T::one()
+        } else if dq < T::zero() {
+            -T::one()
+        } else {
+            T::zero()
+        };
+        let mut dry = self.coulomb.clone();
+        if self.stribeck_velocity != T::zero() {
+            let x = dq.clone() / self.stribeck_velocity.clone();
+            dry += (self.static_friction.clone() - self.coulomb.clone()) * (-x.clone() * x).exp();
+        }
+        dry * sign + self.viscous.clone() * dq
+    }
+
+    /// Motor torque giving the joint torque `tau` at the joint speed `dq`.
+    ///
+    /// The gear loses `efficiency` on the way out when the motor drives the
+    /// joint, and on the way in when the joint back-drives the motor.
+    pub fn motor_torque(&self, tau: T, dq: T) -> T {
+        let n = self.gear_ratio.clone();
+        if tau.clone() * dq >= T::zero() {
+            tau / (n * self.efficiency.clone())
+        } else {
+            tau * self.efficiency.clone() / n
+        }
+    }
+
+    /// Joint torque of the motor torque `tau_motor` at the joint speed `dq`,
+    /// the inverse of `motor_torque`.
+    pub fn joint_torque(&self, tau_motor: T, dq: T) -> T {
+        let n = self.gear_ratio.clone();
+        if tau_motor.clone() * dq >= T::zero() {
+            tau_motor * n * self.efficiency.clone()
+        } else {
+            tau_motor * n / self.efficiency.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_actuator {
+    use crate::actuator::Actuator;
+    use approx::assert_relative_eq;
+
+    fn actuator() -> Actuator<f64> {
+        let mut act = Actuator::new();
+        act.gear_ratio = 100.0;
+        act.rotor_inertia = 2.0e-6;
+        act.efficiency = 0.8;
+        act.coulomb = 0.5;
+        act.static_friction = 0.8;
+        act.stribeck_velocity = 0.1;
+        act.viscous = 0.2;
+        act
+    }
+
+    #[test]
+    fn friction() {
+        let act = actuator();
+        assert_relative_eq!(act.armature(), 0.02, epsilon = 1.0e-12);
+        assert_eq!(act.friction(0.0), 0.0);
+        // static friction just off rest, coulomb and viscous far from it
+        assert_relative_eq!(act.friction(1.0e-9), 0.8, epsilon = 1.0e-6);
+        assert_relative_eq!(act.friction(-1.0e-9), -0.8, epsilon = 1.0e-6);
+        assert_relative_eq!(act.friction(5.0), 0.5 + 1.0, epsilon = 1.0e-9);
+        assert_relative_eq!(act.friction(-5.0), -1.5, epsilon = 1.0e-9);
+
+        let mut act = act;
+        act.stribeck_velocity = 0.0;
+        assert_relative_eq!(act.friction(1.0e-9), 0.5, epsilon = 1.0e-6);
+    }
+
+    #[test]
+    fn transmission() {
+        let act = actuator();
+        // driving: the motor supplies the loss
+        assert_relative_eq!(act.motor_torque(8.0, 1.0), 0.1, epsilon = 1.0e-12);
+        // braking: the joint supplies it
+        assert_relative_eq!(act.motor_torque(8.0, -1.0), 0.064, epsilon = 1.0e-12);
+        for (tau, dq) in [(8.0, 1.0), (8.0, -1.0), (-3.0, 2.0), (-3.0, -2.0)] {
+            let motor = act.motor_torque(tau, dq);
+            assert_relative_eq!(act.joint_torque(motor, dq), tau, epsilon = 1.0e-12);
+        }
+    }
+}
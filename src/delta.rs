@@ -0,0 +1,202 @@
+use crate::ik::IkError;
+
+/// Kinematics of a rotary delta robot with three arms 120° apart.
+///
+/// The base frame has its origin at the center of the shoulder joints and +z
+/// up, with the effector working below. Arm `i` sits at the azimuth
+/// `i * 120°` from +x. `q[i]` is the angle of its upper arm below the
+/// horizontal, zero with the arm pointing straight out.
+///
+/// - `base_radius`: distance from the base center to the shoulder axes.
+/// - `effector_radius`: distance from the effector center to the forearm joints.
+/// - `arm`: upper arm length.
+/// - `forearm`: length of the parallelogram forearm.
+pub struct Delta<T> {
+    pub base_radius: T,
+    pub effector_radius: T,
+    pub arm: T,
+    pub forearm: T,
+}
+
+impl<T: na::RealField> Default for Delta<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Delta<T> {
+    pub fn new() -> Self {
+        Self {
+            base_radius: T::zero(),
+            effector_radius: T::zero(),
+            arm: T::zero(),
+            forearm: T::zero(),
+        }
+    }
+
+    fn check(&self) -> Result<(), IkError> {
+        if self.arm <= T::zero() || self.forearm <= T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        Ok(())
+    }
+
+    /// Rotation from the base frame to the frame of arm `i`.
+    fn arm_frame(i: usize) -> na::UnitQuaternion<T> {
+        let azimuth = T::two_pi() * na::convert::<f64, T>(i as f64) / na::convert(3.0);
+        na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), azimuth)
+    }
+
+    /// Elbow of arm `i`, shifted in by the effector radius, in the base frame.
+    fn elbow(&self, i: usize, q: T) -> na::Vector3<T> {
+        let (s, c) = q.sin_cos();
+        Self::arm_frame(i).transform_vector(&na::Vector3::new(
+            self.base_radius.clone() - self.effector_radius.clone() + self.arm.clone() * c,
+            T::zero(),
+            -self.arm.clone() * s,
+        ))
+    }
+
+    /// Arm angles placing the effector center at `p`.
+    pub fn solve(&self, p: &na::Vector3<T>) -> Result<[T; 3], IkError> {
+        self.check()?;
+        let two = T::one() + T::one();
+        let mut q = [T::zero(), T::zero(), T::zero()];
+        for (i, q) in q.iter_mut().enumerate() {
+            let v = Self::arm_frame(i).inverse_transform_vector(p);
+            // forearm joint seen from the shoulder
+            let x = v.x.clone() + self.effector_radius.clone() - self.base_radius.clone();
+            let rho = x.clone().hypot(v.z.clone());
+            if rho == T::zero() {
+                return Err(IkError::TooNear);
+            }
+            let c = (self.arm.clone() * self.arm.clone()
+                + x.clone() * x.clone()
+                + v.y.clone() * v.y.clone()
+                + v.z.clone() * v.z.clone()
+                - self.forearm.clone() * self.forearm.clone())
+                / (two.clone() * self.arm.clone() * rho);
+            if c > T::one() {
+                return Err(IkError::TooFar);
+            }
+            if c < -T::one() {
+                return Err(IkError::TooNear);
+            }
+            // elbow pointing outwards
+            *q = -v.z.clone().atan2(x) - c.acos();
+        }
+        Ok(q)
+    }
+
+    /// Position of the effector center for the arm angles `q`, taking the
+    /// solution below the elbows. Fails with `Singular` when the elbows are
+    /// on a line and the effector is free to swing about it.
+    pub fn forward(&self, q: &[T; 3]) -> Result<na::Vector3<T>, IkError> {
+        self.check()?;
+        let c1 = self.elbow(0, q[0].clone());
+        let c2 = self.elbow(1, q[1].clone());
+        let c3 = self.elbow(2, q[2].clone());
+
+        // intersection of the three spheres of radius `forearm`, which have
+        // a circle in common when their centers are on a line
+        let tol = T::default_epsilon() * (self.arm.clone() + self.forearm.clone());
+        let d = (c2.clone() - c1.clone()).norm();
+        if d <= tol {
+            return Err(IkError::Singular);
+        }
+        let ex = (c2 - c1.clone()) / d.clone();
+        let i = ex.dot(&(c3.clone() - c1.clone()));
+        let ey = c3.clone() - c1.clone() - ex.clone() * i.clone();
+        let j = ey.norm();
+        if j <= tol {
+            return Err(IkError::Singular);
+        }
+        let ey = ey / j.clone();
+        let ez = ex.cross(&ey);
+
+        let two = T::one() + T::one();
+        let x = d / two.clone();
+        let y =
+            (i.clone() * i.clone() + j.clone() * j.clone()) / (two * j.clone()) - i / j * x.clone();
+        let z_sq = self.forearm.clone() * self.forearm.clone()
+            - x.clone() * x.clone()
+            - y.clone() * y.clone();
+        if z_sq < T::zero() {
+            return Err(IkError::TooFar);
+        }
+        let ez = if ez.z > T::zero() { -ez } else { ez };
+        Ok(c1 + ex * x + ey * y + ez * z_sq.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod test_delta {
+    use crate::delta::Delta;
+    use crate::ik::IkError;
+    use approx::assert_relative_eq;
+
+    fn delta() -> Delta<f64> {
+        let mut delta = Delta::new();
+        delta.base_radius = 0.2;
+        delta.effector_radius = 0.05;
+        delta.arm = 0.3;
+        delta.forearm = 0.8;
+        delta
+    }
+
+    #[test]
+    fn symmetric() {
+        let delta = delta();
+        let p = delta.forward(&[0.3, 0.3, 0.3]).unwrap();
+        assert_relative_eq!(p.x, 0.0, epsilon = 1.0e-12);
+        assert_relative_eq!(p.y, 0.0, epsilon = 1.0e-12);
+        // forearm joint at distance `forearm` from the elbow
+        let h = 0.15 + 0.3 * 0.3_f64.cos();
+        let z = -0.3 * 0.3_f64.sin() - (0.8 * 0.8 - h * h).sqrt();
+        assert_relative_eq!(p.z, z, epsilon = 1.0e-12);
+    }
+
+    #[test]
+    fn round_trip() {
+        let delta = delta();
+        for q in [[0.1, 0.2, 0.3], [-0.4, 0.6, 0.2], [0.9, 0.5, -0.1]] {
+            let p = delta.forward(&q).unwrap();
+            let ans = delta.solve(&p).unwrap();
+            for i in 0..3 {
+                assert_relative_eq!(ans[i], q[i], epsilon = 1.0e-9);
+            }
+        }
+        for p in [
+            na::Vector3::new(0.0, 0.0, -0.7),
+            na::Vector3::new(0.1, -0.2, -0.6),
+        ] {
+            let q = delta.solve(&p).unwrap();
+            assert_relative_eq!(delta.forward(&q).unwrap(), p, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn out_of_workspace() {
+        let mut delta = delta();
+        assert_eq!(
+            delta.solve(&na::Vector3::new(0.0, 0.0, -1.5)),
+            Err(IkError::TooFar)
+        );
+        assert_eq!(
+            delta.solve(&na::Vector3::new(0.0, 0.0, -0.1)),
+            Err(IkError::TooNear)
+        );
+        delta.forearm = 0.1;
+        assert_eq!(delta.forward(&[0.0, 0.0, 0.0]), Err(IkError::TooFar));
+        delta.forearm = 0.8;
+        // elbows folded onto the center line, two of them at the same point
+        let up = 2.0 * core::f64::consts::FRAC_PI_3;
+        assert_eq!(delta.forward(&[up, up, -up]), Err(IkError::Singular));
+        assert_eq!(delta.forward(&[up, -up, up]), Err(IkError::Singular));
+        delta.arm = 0.0;
+        assert_eq!(
+            delta.solve(&na::Vector3::new(0.0, 0.0, -0.7)),
+            Err(IkError::InvalidLength)
+        );
+    }
+}
@@ -19,9 +19,47 @@ impl<T: na::RealField> DH<T> for na::geometry::Isometry3<T> {
     }
 }
 
+/// One row of a DH table, in the convention of `DH::from_dh_param`.
+///
+/// `theta` is the offset added to the joint angle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DHParam<T> {
+    pub a: T,
+    pub alfa: T,
+    pub d: T,
+    pub theta: T,
+}
+
+impl<T: na::RealField> DHParam<T> {
+    pub fn new(a: T, alfa: T, d: T, theta: T) -> Self {
+        Self { a, alfa, d, theta }
+    }
+
+    /// Transform from the previous frame at joint angle `q`.
+    pub fn to_isometry(&self, q: T) -> na::Isometry3<T> {
+        na::Isometry3::from_dh_param(
+            self.a.clone(),
+            self.alfa.clone(),
+            self.d.clone(),
+            self.theta.clone() + q,
+        )
+    }
+}
+
+/// Forward kinematics of the serial chain described by `table`.
+pub fn forward<T: na::RealField>(table: &[DHParam<T>], q: &[T]) -> na::Isometry3<T> {
+    table
+        .iter()
+        .zip(q.iter())
+        .fold(na::Isometry3::identity(), |t, (param, q)| {
+            t * param.to_isometry(q.clone())
+        })
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_range_loop)]
 mod dh_param_tests {
-    use crate::dh::DH;
+    use crate::dh::{forward, DHParam, DH};
     use assert_approx_eq::assert_approx_eq;
     use core::f32::consts::PI;
 
@@ -61,4 +99,19 @@ mod dh_param_tests {
             }
         }
     }
+
+    #[test]
+    fn dh_forward() {
+        let table = [
+            DHParam::new(0.0, 0.0, 1.0, 0.0),
+            DHParam::new(2.0, PI / 2.0, 0.0, PI / 2.0),
+        ];
+        let t = forward(&table, &[PI / 2.0, -PI / 2.0]);
+        let expect = na::Isometry3::from_dh_param(0.0, 0.0, 1.0, PI / 2.0)
+            * na::Isometry3::from_dh_param(2.0, PI / 2.0, 0.0, 0.0);
+        assert_approx_eq!(t.translation.vector.x, expect.translation.vector.x, 0.001);
+        assert_approx_eq!(t.translation.vector.y, expect.translation.vector.y, 0.001);
+        assert_approx_eq!(t.translation.vector.z, expect.translation.vector.z, 0.001);
+        assert_approx_eq!(t.rotation.angle_to(&expect.rotation), 0.0, 0.001);
+    }
 }
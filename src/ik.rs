@@ -1,9 +1,84 @@
-#[allow(unused_imports)]
+mod chain;
+mod leg;
+mod parallel_axes;
+mod spherical_wrist;
+mod srs;
+
+pub use chain::{Ccd, Fabrik};
+pub use leg::{IK3dQuadrupedLeg, IK6dBipedLeg, Knee};
+pub use parallel_axes::{IK6dParallelAxes, ParallelAxesSolution, Singularity};
+pub use spherical_wrist::IK6dSphericalWrist;
+pub use srs::{IK7dSRS, SRSBranch};
+
+use crate::link::Link;
+
+/// Reasons an inverse kinematics solve can fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IkError {
+    /// A link length is zero or negative.
+    InvalidLength,
+    /// The target is farther than the fully stretched arm can reach.
+    TooFar,
+    /// The target is closer than the fully folded arm can reach.
+    TooNear,
+    /// The reference point is parallel to the target, so the elbow plane is undefined.
+    DegenerateReference,
+    /// The kinematic parameters do not match the layout the solver is written for.
+    UnsupportedGeometry,
+    /// A solution exists but leaves the joint limits.
+    JointLimit,
+    /// The iterative solver ran out of iterations before reaching the tolerance.
+    NotConverged,
+    /// The configuration is singular, so the solution is not unique.
+    Singular,
+}
+
+/// Wrap an angle into (-π, π].
+pub fn wrap_angle<T: na::RealField>(theta: T) -> T {
+    let wrapped = theta.clone() - T::two_pi() * ((theta + T::pi()) / T::two_pi()).floor();
+    if wrapped <= -T::pi() {
+        wrapped + T::two_pi()
+    } else {
+        wrapped
+    }
+}
+
+/// Check `q` against the limits of `links`, one link per angle.
+///
+/// A link with `q_min == q_max` is taken as unlimited.
+pub fn check_joint_limits<T: na::RealField>(q: &[T], links: &[Link<T>]) -> Result<(), IkError> {
+    for (q, link) in q.iter().zip(links.iter()) {
+        if link.q_min != link.q_max && (*q < link.q_min || *q > link.q_max) {
+            return Err(IkError::JointLimit);
+        }
+    }
+    Ok(())
+}
 
-/// Robot Kinematics and Dynamics
+/// Inverse kinematics of a 4-DoF arm whose upper arm `a` and forearm `b`
+/// form a triangle with the wrist vector `vc`.
+///
+/// The shoulder is at the origin and all vectors are in the base frame.
+/// The joint angles are
+/// - `ans[0]`: azimuth of the upper arm about +z, measured from +x, in (-π, π].
+/// - `ans[1]`: angle of the upper arm from +z, in [0, π].
+/// - `ans[2]`: roll about the upper arm, in (-π, π]. At zero the forearm stays
+///   in the vertical plane through the upper arm.
+/// - `ans[3]`: elbow bend about `(-sin ans[0], cos ans[0], 0)` at zero roll,
+///   in [0, π]. Zero is fully stretched.
+///
+/// The branches with the other signs are given by `solve_all`.
 pub struct IK4dTriangle<T> {
     pub a: T,
     pub b: T,
+    /// Solve for the closest reachable point instead of returning `TooFar` / `TooNear`.
+    pub closest_reachable: bool,
+}
+
+impl<T: na::RealField> Default for IK4dTriangle<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: na::RealField> IK4dTriangle<T> {
@@ -11,144 +86,270 @@ impl<T: na::RealField> IK4dTriangle<T> {
         Self {
             a: T::zero(),
             b: T::zero(),
+            closest_reachable: false,
         }
     }
 
-    pub fn solve_by_ref_point(&self, vc: &na::Vector3<T>, ref_point: &na::Vector3<T>) -> [T; 4] {
-        let mut ans = [T::zero(), T::zero(), T::zero(), T::zero()];
+    /// Check that `vc` is reachable and return it, moved onto the workspace
+    /// boundary when `closest_reachable` is set.
+    fn reachable_target(&self, vc: &na::Vector3<T>) -> Result<na::Vector3<T>, IkError> {
+        if self.a <= T::zero() || self.b <= T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        let c = vc.norm();
+        let c_max = self.a.clone() + self.b.clone();
+        let c_min = (self.a.clone() - self.b.clone()).abs();
+        if c > c_max {
+            if !self.closest_reachable {
+                return Err(IkError::TooFar);
+            }
+            return Ok(vc * (c_max / c));
+        }
+        // the folded arm reaches the origin when a == b, up to rounding
+        if c < c_min.clone() - T::default_epsilon().sqrt() * c_max {
+            if !self.closest_reachable || c == T::zero() {
+                return Err(IkError::TooNear);
+            }
+            return Ok(vc * (c_min / c));
+        }
+        Ok(vc.clone())
+    }
 
-        let c_squared =
-            vc.x.clone() * vc.x.clone() + vc.y.clone() * vc.y.clone() + vc.z.clone() * vc.z.clone();
-        let c = c_squared.sqrt();
-        // cosine formula
-        ans[3] = T::pi()
-            - ((self.a.clone() * self.a.clone() + self.b.clone() * self.b.clone()
-                - c.clone() * c.clone())
-                / (T::from_f32(2.0).unwrap() * self.a.clone() * self.b.clone()))
-            .acos();
+    /// Solve with the elbow in the plane spanned by `vc` and `ref_point`,
+    /// on the side of `ref_point`.
+    pub fn solve_by_ref_point(
+        &self,
+        vc: &na::Vector3<T>,
+        ref_point: &na::Vector3<T>,
+    ) -> Result<[T; 4], IkError> {
+        let mut ans = [T::zero(), T::zero(), T::zero(), T::zero()];
 
+        let vc = self.reachable_target(vc)?;
+        let c = vc.norm();
+        if c == T::zero() {
+            // folded onto itself, with the elbow towards ref_point
+            let u = na::Unit::try_new(ref_point.clone(), T::default_epsilon())
+                .ok_or(IkError::DegenerateReference)?;
+            ans[0] = u.y.clone().atan2(u.x.clone());
+            ans[1] = u.x.clone().hypot(u.y.clone()).atan2(u.z.clone());
+            ans[3] = T::pi();
+            return Ok(ans);
+        }
+        let c_axis = vc.clone() / c.clone();
+        // unit vector perpendicular to vc, towards the side the elbow goes to
+        let ref_axis = na::Unit::try_new(
+            ref_point - c_axis.clone() * c_axis.dot(ref_point),
+            T::default_epsilon(),
+        )
+        .ok_or(IkError::DegenerateReference)?;
+
+        // four times the triangle area (Heron), clamped against rounding on the workspace boundary
+        let (a, b) = (self.a.clone(), self.b.clone());
+        let area4 = ((a.clone() + b.clone() + c.clone())
+            * (-a.clone() + b.clone() + c.clone())
+            * (a.clone() - b.clone() + c.clone())
+            * (a.clone() + b.clone() - c.clone()))
+        .max(T::zero())
+        .sqrt();
+        let (a2, b2, c2) = (a.clone() * a.clone(), b.clone() * b.clone(), c.clone() * c);
+
+        // exterior angle at the elbow
+        ans[3] = area4.clone().atan2(c2.clone() - a2.clone() - b2.clone());
         // angle between vc and va
-        let b_theta = ((self.a.clone() * self.a.clone() + c.clone() * c.clone()
-            - self.b.clone() * self.b.clone())
-            / (T::from_f32(2.0).unwrap() * self.a.clone() * c.clone()))
-        .acos();
-
-        // rotation from axis vertial to ref point vector and vc
-        let rot_c_ref = na::UnitQuaternion::from_axis_angle(
-            &na::Unit::new_normalize(vc.cross(&ref_point)),
-            b_theta.clone(),
-        );
-        let va = rot_c_ref.transform_vector(&vc) * self.a.clone() / c.clone();
-        let vb = vc.clone() - va.clone();
-
-        // // rotation along with vector c
-        // let rot_ref_theta = na::UnitQuaternion::from_axis_angle(
-        //     &na::Unit::new_normalize(vc.clone()),
-        //     ref_theta.clone(),
-        // );
-        // let va = rot_ref_theta.transform_vector(&va_dot);
-        // let vb = rot_ref_theta.transform_vector(&vb_dot);
+        let b_theta = area4.clone().atan2(a2 + c2 - b2);
+
+        let va = (c_axis * b_theta.clone().cos() + ref_axis.as_ref() * b_theta.sin()) * a.clone();
+        let vb = vc - va.clone();
+
         ans[0] = va.y.clone().atan2(va.x.clone());
-        ans[1] = (va.z.clone() / self.a.clone()).acos(); // 精度悪いめ
+        ans[1] = va.x.clone().hypot(va.y.clone()).atan2(va.z.clone());
 
-        let rot_ans0 = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), ans[0].clone());
-        let rot_ans3 = na::UnitQuaternion::from_axis_angle(
-            &na::Unit::new_normalize(rot_ans0.transform_vector(&na::Vector3::y_axis())),
-            ans[3].clone(),
-        );
+        // roll of the forearm about the upper arm, measured from the vertical plane
+        if area4 > T::zero() {
+            let (_, e, w) = Self::upper_arm_frame(&ans);
+            ans[2] = vb.dot(&e).atan2(vb.dot(&w));
+        }
 
-        let vb_dot_dot = rot_ans3.transform_vector(&va) * self.b.clone() / self.a.clone();
+        Ok(ans)
+    }
 
-        // vector a component of vector b
-        let va_tmp = va.clone() * va.dot(&vb) / self.a.clone() / self.a.clone();
-        // let va_tmp2 = va.clone() * va.dot(&vb_dot_dot) / self.a.clone() / self.a.clone();
-        // assert_eq!(va_tmp, va_tmp2);
+    /// Solve with the elbow rotated by `ref_theta` about `vc`, starting from
+    /// the side of +z, or of +x when `vc` is vertical.
+    pub fn solve(&self, vc: &na::Vector3<T>, ref_theta: &T) -> Result<[T; 4], IkError> {
+        let ref_point = match na::Unit::try_new(vc.clone(), T::zero()) {
+            Some(c_axis) => {
+                let rot_c_ref = na::UnitQuaternion::from_axis_angle(&c_axis, ref_theta.clone());
+                rot_c_ref.transform_vector(&Self::reference_axis(&c_axis))
+            }
+            None => na::Vector3::z(),
+        };
 
-        let vb_tmp = vb_dot_dot.clone() - va_tmp.clone();
-        let vb_tmp2 = vb.clone() - va_tmp.clone();
+        self.solve_by_ref_point(vc, &ref_point)
+    }
 
-        let vb_tmp_cross = vb_tmp.cross(&vb_tmp2);
-        ans[2] = va.clone().dot(&vb_tmp_cross).signum()
-            * vb_tmp_cross.dot(&vb_tmp_cross).sqrt().atan2(vb_tmp.dot(&vb_tmp2));
+    /// Every joint-space branch placing the elbow at the same point.
+    ///
+    /// The branches are, in order, the solution of `solve_by_ref_point`,
+    /// the flipped elbow `[q0, q1, q2 + π, -q3]`,
+    /// the flipped shoulder `[q0 + π, -q1, q2 + π, q3]`,
+    /// and both flipped `[q0 + π, -q1, q2, -q3]`, wrapped into (-π, π].
+    pub fn solve_all_by_ref_point(
+        &self,
+        vc: &na::Vector3<T>,
+        ref_point: &na::Vector3<T>,
+    ) -> Result<[[T; 4]; 4], IkError> {
+        let ans = self.solve_by_ref_point(vc, ref_point)?;
+        Ok(Self::branches(&ans))
+    }
 
-        ans
-        // 👺原点と方向の定義も必要
+    /// Every joint-space branch of `solve`, in the order of `solve_all_by_ref_point`.
+    pub fn solve_all(&self, vc: &na::Vector3<T>, ref_theta: &T) -> Result<[[T; 4]; 4], IkError> {
+        let ans = self.solve(vc, ref_theta)?;
+        Ok(Self::branches(&ans))
     }
-    pub fn solve(&self, vc: &na::Vector3<T>, ref_theta: &T) -> [T; 4] {
-        
-        let rot_c_ref = na::UnitQuaternion::from_axis_angle(
-            &na::Unit::new_normalize(vc.clone()),
-            ref_theta.clone(),
-        );
-        let ref_point = rot_c_ref.transform_vector(&na::Vector3::z_axis());
 
-        self.solve_by_ref_point(vc, &ref_point)
+    /// The branch of `solve_all` closest to `current`.
+    ///
+    /// Each returned angle is the one nearest to the matching entry of `current`,
+    /// so it may lie outside (-π, π] but never jumps by a full turn.
+    pub fn solve_nearest(
+        &self,
+        vc: &na::Vector3<T>,
+        ref_theta: &T,
+        current: &[T; 4],
+    ) -> Result<[T; 4], IkError> {
+        let branches = self.solve_all(vc, ref_theta)?;
+
+        let mut best = 0;
+        let mut best_dist = T::max_value().unwrap();
+        for (i, branch) in branches.iter().enumerate() {
+            let mut dist = T::zero();
+            for j in 0..4 {
+                let d = wrap_angle(branch[j].clone() - current[j].clone());
+                dist += d.clone() * d;
+            }
+            if dist < best_dist {
+                best = i;
+                best_dist = dist;
+            }
+        }
 
-        // let mut ans = [T::zero(), T::zero(), T::zero(), T::zero()];
-        // let c_squared =
-        //     vc.x.clone() * vc.x.clone() + vc.y.clone() * vc.y.clone() + vc.z.clone() * vc.z.clone();
-        // let c = c_squared.sqrt();
-        // // cosine formula
-        // ans[3] = T::pi()
-        //     - ((self.a.clone() * self.a.clone() + self.b.clone() * self.b.clone()
-        //         - c.clone() * c.clone())
-        //         / (T::from_f32(2.0).unwrap() * self.a.clone() * self.b.clone()))
-        //     .acos();
-
-        // // angle between vc and va
-        // let b_theta = ((self.a.clone() * self.a.clone() + c.clone() * c.clone()
-        //     - self.b.clone() * self.b.clone())
-        //     / (T::from_f32(2.0).unwrap() * self.a.clone() * c.clone()))
-        // .acos();
-
-        // // rotation from axis vertial to z axis and vc👺
-        // let rot_cz = na::UnitQuaternion::from_axis_angle(
-        //     &na::Unit::new_normalize(vc.cross(&na::Vector3::z_axis())),
-        //     b_theta.clone(),
-        // );
-        // let va_dot = rot_cz.transform_vector(&vc) * self.a.clone() / c.clone();
-        // let vb_dot = vc.clone() - va_dot.clone();
-
-        // // rotation along with vector c
-        // let rot_ref_theta = na::UnitQuaternion::from_axis_angle(
-        //     &na::Unit::new_normalize(vc.clone()),
-        //     ref_theta.clone(),
-        // );
-        // let va = rot_ref_theta.transform_vector(&va_dot);
-        // let vb = rot_ref_theta.transform_vector(&vb_dot);
-        // ans[0] = va.y.clone().atan2(va.x.clone());
-        // ans[1] = (va.z.clone() / self.a.clone()).acos(); // 精度悪いめ
-
-        // let rot_ans0 = na::UnitQuaternion::from_axis_angle(&na::Vector3::z_axis(), ans[0].clone());
-        // let rot_ans3 = na::UnitQuaternion::from_axis_angle(
-        //     &na::Unit::new_normalize(rot_ans0.transform_vector(&na::Vector3::y_axis())),
-        //     ans[3].clone(),
-        // );
-
-        // let vb_dot_dot = rot_ans3.transform_vector(&va) * self.b.clone() / self.a.clone();
-
-        // // vector a component of vector b
-        // let va_tmp = va.clone() * va.dot(&vb) / self.a.clone() / self.a.clone();
-        // // let va_tmp2 = va.clone() * va.dot(&vb_dot_dot) / self.a.clone() / self.a.clone();
-        // // assert_eq!(va_tmp, va_tmp2);
-
-        // let vb_tmp = vb_dot_dot.clone() - va_tmp.clone();
-        // let vb_tmp2 = vb.clone() - va_tmp.clone();
-
-        // let vb_tmp_cross = vb_tmp.cross(&vb_tmp2);
-        // ans[2] = va.clone().dot(&vb_tmp_cross).signum()
-        //     * vb_tmp_cross.dot(&vb_tmp_cross).sqrt().atan2(vb_tmp.dot(&vb_tmp2));
-
-        // ans
-        // // 👺原点と方向の定義も必要
+        let mut ans = branches[best].clone();
+        for j in 0..4 {
+            ans[j] = current[j].clone() + wrap_angle(ans[j].clone() - current[j].clone());
+        }
+        Ok(ans)
+    }
+
+    /// Forward kinematics: the wrist vector `vc` and the reference-plane angle
+    /// `ref_theta` for which `solve` returns `ans`.
+    ///
+    /// `ref_theta` is measured from +z as in `solve`, or from +x when `vc` is
+    /// vertical. It is zero when it is undefined, i.e. when the arm is fully
+    /// stretched or folded.
+    pub fn forward(&self, ans: &[T; 4]) -> (na::Vector3<T>, T) {
+        let (u, e, w) = Self::upper_arm_frame(ans);
+        let (s2, c2) = ans[2].clone().sin_cos();
+        let (s3, c3) = ans[3].clone().sin_cos();
+
+        let va = u.clone() * self.a.clone();
+        let vb = (u * c3 + (w * c2 + e * s2) * s3) * self.b.clone();
+        let vc = va.clone() + vb;
+
+        let ref_theta = match na::Unit::try_new(vc.clone(), T::zero()) {
+            Some(c_axis) => {
+                let r = Self::reference_axis(&c_axis);
+                let r_perp = r.clone() - c_axis.as_ref() * c_axis.dot(&r);
+                let va_perp = va.clone() - c_axis.as_ref() * c_axis.dot(&va);
+                c_axis
+                    .dot(&r_perp.cross(&va_perp))
+                    .atan2(r_perp.dot(&va_perp))
+            }
+            None => T::zero(),
+        };
+
+        (vc, ref_theta)
     }
-}
 
+    /// Jacobian of the wrist vector `vc` with respect to `ans`.
+    pub fn jacobian(&self, ans: &[T; 4]) -> na::Matrix3x4<T> {
+        let (u, e, w) = Self::upper_arm_frame(ans);
+        let (s0, c0) = ans[0].clone().sin_cos();
+        let (s1, c1) = ans[1].clone().sin_cos();
+        let (s2, c2) = ans[2].clone().sin_cos();
+        let (s3, c3) = ans[3].clone().sin_cos();
+        // derivative of e with respect to ans[0]
+        let de = na::Vector3::new(-c0, -s0, T::zero());
+
+        let d0 = e.clone() * (s1.clone() * c3.clone())
+            + (e.clone() * (c1.clone() * c2.clone()) + de * s2.clone()) * s3.clone();
+        let d1 = w.clone() * c3.clone() - u.clone() * (s3.clone() * c2.clone());
+        let d2 = (e.clone() * c2.clone() - w.clone() * s2.clone()) * s3.clone();
+        let d3 = (w.clone() * c2 + e.clone() * s2) * c3 - u * s3;
+
+        let mut jac = na::Matrix3x4::zeros();
+        jac.set_column(0, &(e * (self.a.clone() * s1) + d0 * self.b.clone()));
+        jac.set_column(1, &(w * self.a.clone() + d1 * self.b.clone()));
+        jac.set_column(2, &(d2 * self.b.clone()));
+        jac.set_column(3, &(d3 * self.b.clone()));
+        jac
+    }
+
+    /// Axis `ref_theta` is measured from: +z, or +x when `c_axis` is too
+    /// close to vertical for +z to give a direction across it.
+    fn reference_axis(c_axis: &na::Unit<na::Vector3<T>>) -> na::Vector3<T> {
+        let z_perp = na::Vector3::z() - c_axis.as_ref() * c_axis.z.clone();
+        if z_perp.norm() > T::default_epsilon().sqrt() {
+            na::Vector3::z()
+        } else {
+            na::Vector3::x()
+        }
+    }
+
+    /// Orthonormal frame of the upper arm: its direction `u`, the elbow axis
+    /// `e` at zero roll and `w = e × u`.
+    fn upper_arm_frame(ans: &[T; 4]) -> (na::Vector3<T>, na::Vector3<T>, na::Vector3<T>) {
+        let (s0, c0) = ans[0].clone().sin_cos();
+        let (s1, c1) = ans[1].clone().sin_cos();
+        let u = na::Vector3::new(s1.clone() * c0.clone(), s1.clone() * s0.clone(), c1.clone());
+        let e = na::Vector3::new(-s0.clone(), c0.clone(), T::zero());
+        let w = na::Vector3::new(c0 * c1.clone(), s0 * c1, -s1);
+        (u, e, w)
+    }
+
+    fn branches(ans: &[T; 4]) -> [[T; 4]; 4] {
+        let [q0, q1, q2, q3] = ans.clone();
+        let pi = T::pi();
+        [
+            [q0.clone(), q1.clone(), q2.clone(), q3.clone()],
+            [
+                q0.clone(),
+                q1.clone(),
+                wrap_angle(q2.clone() + pi.clone()),
+                -q3.clone(),
+            ],
+            [
+                wrap_angle(q0.clone() + pi.clone()),
+                -q1.clone(),
+                wrap_angle(q2.clone() + pi.clone()),
+                q3.clone(),
+            ],
+            [wrap_angle(q0 + pi), -q1, q2, -q3],
+        ]
+    }
+}
 
 pub struct IK4dExtendedTriangle<T> {
     pub a: T,
     pub b: T,
-    ik4dtriangle :IK4dTriangle<T>,
+    #[allow(dead_code)]
+    ik4dtriangle: IK4dTriangle<T>,
+}
+
+impl<T: na::RealField> Default for IK4dExtendedTriangle<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: na::RealField> IK4dExtendedTriangle<T> {
@@ -159,12 +360,12 @@ impl<T: na::RealField> IK4dExtendedTriangle<T> {
             ik4dtriangle: IK4dTriangle::new(),
         }
     }
-
 }
 
 #[cfg(test)]
+#[allow(clippy::unnecessary_cast, clippy::excessive_precision)]
 mod test_ik {
-    use crate::ik::IK4dTriangle;
+    use crate::ik::{wrap_angle, IK4dTriangle, IkError};
     use approx::assert_relative_eq;
 
     trait Deg {
@@ -183,32 +384,43 @@ mod test_ik {
 
         ik.a = 3.0;
         ik.b = 3.0;
-        let ans = ik.solve(&na::Vector3::new(3.0, 0.0, 0.0), &0.0);
+        let ans = ik.solve(&na::Vector3::new(3.0, 0.0, 0.0), &0.0).unwrap();
         assert_relative_eq!(ans[0], 0.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[1], core::f32::consts::PI / 6.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[2], 0.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[3], core::f32::consts::PI * 2.0 / 3.0, epsilon = 1.0e-6);
         ik.a = 3.0;
         ik.b = 3.0;
-        let ans = ik.solve(&na::Vector3::new(
-            3.0 / (2.0 as f32).sqrt(),
-            3.0 / (2.0 as f32).sqrt(),
-            0.0,
-        ), &0.0);
+        let ans = ik
+            .solve(
+                &na::Vector3::new(3.0 / (2.0 as f32).sqrt(), 3.0 / (2.0 as f32).sqrt(), 0.0),
+                &0.0,
+            )
+            .unwrap();
         assert_relative_eq!(ans[0], core::f32::consts::PI / 4.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[1], core::f32::consts::PI / 6.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[2], 0.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[3], core::f32::consts::PI * 2.0 / 3.0, epsilon = 1.0e-6);
         ik.a = 3.0;
         ik.b = 3.0;
-        let ans = ik.solve(&na::Vector3::new(3.0, 0.0, 0.0), &(core::f32::consts::PI / 2.0));
+        let ans = ik
+            .solve(
+                &na::Vector3::new(3.0, 0.0, 0.0),
+                &(core::f32::consts::PI / 2.0),
+            )
+            .unwrap();
         assert_relative_eq!(ans[0], -core::f32::consts::PI / 3.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[1], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[2], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[3], core::f32::consts::PI * 2.0 / 3.0, epsilon = 1.0e-6);
         ik.a = 3.0;
         ik.b = 3.0;
-        let ans = ik.solve(&na::Vector3::new(3.0, 0.0, 0.0), &(-core::f32::consts::PI / 2.0));
+        let ans = ik
+            .solve(
+                &na::Vector3::new(3.0, 0.0, 0.0),
+                &(-core::f32::consts::PI / 2.0),
+            )
+            .unwrap();
         assert_relative_eq!(ans[0], core::f32::consts::PI / 3.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[1], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
         assert_relative_eq!(ans[2], -core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
@@ -220,10 +432,258 @@ mod test_ik {
         let mut ik = IK4dTriangle::<f32>::new();
         ik.a = 1.0;
         ik.b = (3.0 as f32).sqrt();
-        let ans = ik.solve(&na::Vector3::new(2.0, 0.0, 0.0), &(core::f32::consts::PI / 4.));
+        let ans = ik
+            .solve(
+                &na::Vector3::new(2.0, 0.0, 0.0),
+                &(core::f32::consts::PI / 4.),
+            )
+            .unwrap();
         assert_relative_eq!(ans[0], -50.76847952.deg2rad(), epsilon = 1.0e-6);
         assert_relative_eq!(ans[1], 52.23875609.deg2rad(), epsilon = 1.0e-6);
         assert_relative_eq!(ans[2], 63.43494882.deg2rad(), epsilon = 1.0e-6);
         assert_relative_eq!(ans[3], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
     }
+    #[test]
+    fn ik_4dof_unreachable() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        assert_eq!(
+            ik.solve(&na::Vector3::new(1.0, 0.0, 0.0), &0.0),
+            Err(IkError::InvalidLength)
+        );
+
+        ik.a = 2.0;
+        ik.b = 1.0;
+        assert_eq!(
+            ik.solve(&na::Vector3::new(3.5, 0.0, 0.0), &0.0),
+            Err(IkError::TooFar)
+        );
+        assert_eq!(
+            ik.solve(&na::Vector3::new(0.5, 0.0, 0.0), &0.0),
+            Err(IkError::TooNear)
+        );
+        assert_eq!(ik.solve(&na::Vector3::zeros(), &0.0), Err(IkError::TooNear));
+        assert_eq!(
+            ik.solve_by_ref_point(
+                &na::Vector3::new(2.0, 0.0, 0.0),
+                &na::Vector3::new(-1.0, 0.0, 0.0)
+            ),
+            Err(IkError::DegenerateReference)
+        );
+    }
+
+    #[test]
+    fn ik_4dof_closest_reachable() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 2.0;
+        ik.b = 1.0;
+        ik.closest_reachable = true;
+
+        // stretched straight along the target
+        let ans = ik.solve(&na::Vector3::new(0.0, 5.0, 0.0), &0.0).unwrap();
+        assert_relative_eq!(ans[0], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ans[1], core::f32::consts::PI / 2.0, epsilon = 1.0e-3);
+        assert_relative_eq!(ans[3], 0.0, epsilon = 1.0e-3);
+
+        // folded back onto itself
+        let ans = ik.solve(&na::Vector3::new(0.0, 0.1, 0.0), &0.0).unwrap();
+        assert_relative_eq!(ans[3], core::f32::consts::PI, epsilon = 1.0e-3);
+        for v in ans {
+            assert!(v.is_finite());
+        }
+
+        assert_eq!(ik.solve(&na::Vector3::zeros(), &0.0), Err(IkError::TooNear));
+    }
+
+    #[test]
+    fn ik_4dof_folded_to_origin() {
+        // equal arms fold onto themselves to reach the shoulder
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 1.5;
+        ik.b = 1.5;
+        for vc in [na::Vector3::zeros(), na::Vector3::new(0.0, 1.0e-7, 0.0)] {
+            let ans = ik.solve(&vc, &0.0).unwrap();
+            assert_relative_eq!(ans[3], core::f32::consts::PI, epsilon = 1.0e-6);
+            assert_relative_eq!(ik.forward(&ans).0, vc, epsilon = 1.0e-6);
+        }
+        let ans = ik
+            .solve_by_ref_point(&na::Vector3::zeros(), &na::Vector3::new(1.0, 0.0, 0.0))
+            .unwrap();
+        assert_relative_eq!(ans[1], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
+        assert_eq!(
+            ik.solve_by_ref_point(&na::Vector3::zeros(), &na::Vector3::zeros()),
+            Err(IkError::DegenerateReference)
+        );
+    }
+
+    #[test]
+    fn ik_fk_4dof_vertical_wrist() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 1.0;
+        ik.b = 1.0;
+        let pi = core::f32::consts::PI;
+        let (vc, ref_theta) = ik.forward(&[0.0, pi / 3.0, 0.0, -2.0 * pi / 3.0]);
+        assert_relative_eq!(vc, na::Vector3::new(0.0, 0.0, 1.0), epsilon = 1.0e-6);
+        assert_relative_eq!(ref_theta, 0.0, epsilon = 1.0e-6);
+
+        // the elbow goes round the vertical wrist from +x
+        for ref_theta in [0.0, 0.7, -2.0, pi] {
+            for vc in [
+                na::Vector3::new(0.0, 0.0, 1.0),
+                na::Vector3::new(0.0, 0.0, -1.5),
+            ] {
+                let ans = ik.solve(&vc, &ref_theta).unwrap();
+                let (fk_vc, fk_theta) = ik.forward(&ans);
+                assert_relative_eq!(fk_vc, vc, epsilon = 1.0e-5);
+                assert_relative_eq!(wrap_angle(fk_theta - ref_theta), 0.0, epsilon = 1.0e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn wrap() {
+        let pi = core::f32::consts::PI;
+        assert_relative_eq!(wrap_angle(0.5_f32), 0.5, epsilon = 1.0e-6);
+        assert_relative_eq!(wrap_angle(pi), pi, epsilon = 1.0e-6);
+        assert_relative_eq!(wrap_angle(-pi), pi, epsilon = 1.0e-6);
+        assert_relative_eq!(wrap_angle(3.0 * pi / 2.0), -pi / 2.0, epsilon = 1.0e-6);
+        assert_relative_eq!(wrap_angle(-5.0 * pi / 2.0), -pi / 2.0, epsilon = 1.0e-5);
+    }
+
+    #[test]
+    fn ik_4dof_all_branches() {
+        let pi = core::f32::consts::PI;
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 3.0;
+        ik.b = 3.0;
+        let vc = na::Vector3::new(3.0, 0.0, 0.0);
+        let all = ik.solve_all(&vc, &(pi / 2.0)).unwrap();
+        let expect = [
+            [-pi / 3.0, pi / 2.0, pi / 2.0, pi * 2.0 / 3.0],
+            [-pi / 3.0, pi / 2.0, -pi / 2.0, -pi * 2.0 / 3.0],
+            [pi * 2.0 / 3.0, -pi / 2.0, -pi / 2.0, pi * 2.0 / 3.0],
+            [pi * 2.0 / 3.0, -pi / 2.0, pi / 2.0, -pi * 2.0 / 3.0],
+        ];
+        for (ans, e) in all.iter().zip(expect.iter()) {
+            for j in 0..4 {
+                assert_relative_eq!(ans[j], e[j], epsilon = 1.0e-5);
+            }
+        }
+
+        // stay on the branch the arm is already in
+        let current = [2.0, -1.5, -1.6, 2.0];
+        let ans = ik.solve_nearest(&vc, &(pi / 2.0), &current).unwrap();
+        for j in 0..4 {
+            assert_relative_eq!(ans[j], expect[2][j], epsilon = 1.0e-5);
+        }
+
+        // no full-turn jump across ±π
+        let current = [2.0 - 2.0 * pi, -1.5, -1.6, 2.0];
+        let ans = ik.solve_nearest(&vc, &(pi / 2.0), &current).unwrap();
+        assert_relative_eq!(ans[0], expect[2][0] - 2.0 * pi, epsilon = 1.0e-5);
+    }
+
+    #[test]
+    fn fk_4dof_equilateral_triangle() {
+        let pi = core::f32::consts::PI;
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 3.0;
+        ik.b = 3.0;
+        let (vc, ref_theta) = ik.forward(&[-pi / 3.0, pi / 2.0, pi / 2.0, pi * 2.0 / 3.0]);
+        assert_relative_eq!(vc, na::Vector3::new(3.0, 0.0, 0.0), epsilon = 1.0e-5);
+        assert_relative_eq!(ref_theta, pi / 2.0, epsilon = 1.0e-5);
+    }
+
+    #[test]
+    fn ik_fk_4dof_round_trip() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 1.2;
+        ik.b = 0.8;
+        for i in 0..8 {
+            for j in 0..8 {
+                for k in 0..3 {
+                    let azimuth = -3.0 + 0.75 * i as f32;
+                    let elevation = -1.4 + 0.4 * j as f32;
+                    let c = 0.6 + 0.6 * k as f32;
+                    let vc = na::Vector3::new(
+                        c * elevation.cos() * azimuth.cos(),
+                        c * elevation.cos() * azimuth.sin(),
+                        c * elevation.sin(),
+                    );
+                    let ref_theta = -2.5 + 0.6 * (i + j) as f32 % 5.0;
+                    for ans in ik.solve_all(&vc, &ref_theta).unwrap() {
+                        let (fk_vc, fk_ref_theta) = ik.forward(&ans);
+                        assert_relative_eq!(fk_vc, vc, epsilon = 1.0e-4);
+                        assert_relative_eq!(
+                            wrap_angle(fk_ref_theta - ref_theta),
+                            0.0,
+                            epsilon = 1.0e-3
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fk_ik_4dof_round_trip() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 1.0;
+        ik.b = 1.5;
+        for i in 0..6 {
+            for j in 0..5 {
+                let ans = [
+                    -2.5 + 1.0 * i as f32,
+                    0.3 + 0.5 * j as f32,
+                    -2.0 + 0.7 * (i + j) as f32 % 4.0,
+                    0.2 + 0.5 * j as f32,
+                ];
+                let (vc, ref_theta) = ik.forward(&ans);
+                let nearest = ik.solve_nearest(&vc, &ref_theta, &ans).unwrap();
+                for k in 0..4 {
+                    assert_relative_eq!(nearest[k], ans[k], epsilon = 2.0e-3);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn jacobian_4dof() {
+        let mut ik = IK4dTriangle::<f64>::new();
+        ik.a = 1.0;
+        ik.b = 1.5;
+        let ans = [0.3, 0.7, -1.1, 0.9];
+        let jac = ik.jacobian(&ans);
+        let h = 1.0e-6;
+        for k in 0..4 {
+            let mut plus = ans;
+            let mut minus = ans;
+            plus[k] += h;
+            minus[k] -= h;
+            let diff = (ik.forward(&plus).0 - ik.forward(&minus).0) / (2.0 * h);
+            assert_relative_eq!(jac.column(k).into_owned(), diff, epsilon = 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn ik_4dof_near_pole_f32() {
+        let mut ik = IK4dTriangle::<f32>::new();
+        ik.a = 1.0;
+        ik.b = 1.5;
+        for ans1 in [1.0e-3, 1.0e-2, core::f32::consts::PI - 1.0e-3] {
+            let ans = [0.7, ans1, 0.4, 1.2];
+            let (vc, ref_theta) = ik.forward(&ans);
+            let solved = ik.solve(&vc, &ref_theta).unwrap();
+            assert_relative_eq!(solved[1], ans1, epsilon = 1.0e-5);
+            assert_relative_eq!(solved[3], 1.2, epsilon = 1.0e-5);
+            assert_relative_eq!(ik.forward(&solved).0, vc, epsilon = 1.0e-5);
+        }
+
+        // the upper arm exactly on the pole
+        let vc = na::Vector3::new(1.5, 0.0, 1.0);
+        let ans = ik
+            .solve_by_ref_point(&vc, &na::Vector3::new(0.0, 0.0, 1.0))
+            .unwrap();
+        assert_relative_eq!(ans[1], 0.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ik.forward(&ans).0, vc, epsilon = 1.0e-6);
+    }
 }
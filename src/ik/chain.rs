@@ -0,0 +1,389 @@
+use heapless::Vec;
+
+use super::IkError;
+use crate::link::Link;
+use crate::rkd::Rkd;
+
+/// Ids of the links from the root to `tip`.
+fn path<T>(rkd: &Rkd<T>, tip: usize) -> Vec<usize, 256> {
+    let mut ids = Vec::<usize, 256>::new();
+    let mut i = tip;
+    while i != 0 {
+        ids.push(i).unwrap();
+        i = rkd.links[i].parent as usize;
+    }
+    ids.push(0).unwrap();
+    ids.reverse();
+    ids
+}
+
+/// Chain joints moving `tip`, after checking that `target` is within reach
+/// of the stretched chain.
+fn reachable_joints<T: na::RealField>(
+    rkd: &Rkd<T>,
+    tip: usize,
+    target: &na::Vector3<T>,
+) -> Result<Vec<usize, 256>, IkError> {
+    let ids = path(rkd, tip);
+    // the joint of the tip link itself does not move its origin
+    if ids.len() < 3 {
+        return Err(IkError::UnsupportedGeometry);
+    }
+    let mut reach = T::zero();
+    for i in ids[2..].iter() {
+        reach += rkd.links[*i].b.norm();
+    }
+    if (target.clone() - rkd.links[ids[1]].p.clone()).norm() > reach {
+        return Err(IkError::TooFar);
+    }
+    let mut joints = Vec::new();
+    joints.extend_from_slice(&ids[1..ids.len() - 1]).unwrap();
+    Ok(joints)
+}
+
+/// `from` and `to` projected onto the plane normal to `axis`.
+fn in_plane<T: na::RealField>(
+    axis: &na::Vector3<T>,
+    from: &na::Vector3<T>,
+    to: &na::Vector3<T>,
+) -> (na::Vector3<T>, na::Vector3<T>) {
+    (
+        from.clone() - axis.clone() * axis.dot(from),
+        to.clone() - axis.clone() * axis.dot(to),
+    )
+}
+
+/// Angle about `axis` turning `from` onto `to`, both seen in the plane normal to `axis`.
+fn angle_about<T: na::RealField>(
+    axis: &na::Vector3<T>,
+    from: &na::Vector3<T>,
+    to: &na::Vector3<T>,
+) -> T {
+    let (from, to) = in_plane(axis, from, to);
+    axis.dot(&from.cross(&to)).atan2(from.dot(&to))
+}
+
+/// Turn the joint of `link` by `delta`, kept within its limits.
+/// Returns the angle actually turned.
+fn turn<T: na::RealField>(link: &mut Link<T>, delta: T) -> T {
+    let mut q = link.q.clone() + delta;
+    if link.q_min != link.q_max {
+        q = q.max(link.q_min.clone()).min(link.q_max.clone());
+    }
+    let turned = q.clone() - link.q.clone();
+    link.q = q;
+    turned
+}
+
+/// Cyclic coordinate descent on an `Rkd` chain.
+///
+/// Each iteration turns the joints from the tip towards the root, one at a
+/// time, to bring the position `p` of the tip link onto the target.
+pub struct Ccd<T> {
+    pub max_iterations: usize,
+    pub tolerance: T,
+}
+
+impl<T: na::RealField> Default for Ccd<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Ccd<T> {
+    pub fn new() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: T::default_epsilon().sqrt(),
+        }
+    }
+
+    /// Move the joints between the root and `tip` so that `rkd.links[tip].p`
+    /// reaches `target`, and return the iterations used.
+    ///
+    /// The kinematics of `rkd` must be up to date. An unreachable target, or
+    /// a `tip` with no joint between it and the root, fails with `TooFar` or
+    /// `UnsupportedGeometry` and leaves `rkd` untouched. Otherwise `rkd` is
+    /// left at the last iterate, with its kinematics updated, also when the
+    /// solver does not converge.
+    pub fn solve(
+        &self,
+        rkd: &mut Rkd<T>,
+        tip: usize,
+        target: &na::Vector3<T>,
+    ) -> Result<usize, IkError> {
+        let joints = reachable_joints(rkd, tip, target)?;
+
+        for iteration in 0..self.max_iterations {
+            let mut end = rkd.links[tip].p.clone();
+            if (end.clone() - target.clone()).norm() <= self.tolerance {
+                return Ok(iteration);
+            }
+            // turning a joint only moves the joints already visited
+            for &j in joints.iter().rev() {
+                let pivot = rkd.links[j].p.clone();
+                let axis = rkd.links[j].r_quat.transform_vector(&rkd.links[j].a);
+                let delta = angle_about(
+                    &axis,
+                    &(end.clone() - pivot.clone()),
+                    &(target.clone() - pivot.clone()),
+                );
+                let turned = turn(&mut rkd.links[j], delta);
+                let rot =
+                    na::UnitQuaternion::from_axis_angle(&na::Unit::new_unchecked(axis), turned);
+                end = pivot.clone() + rot.transform_vector(&(end - pivot));
+            }
+            rkd.update_kinematic_relationship();
+        }
+        if (rkd.links[tip].p.clone() - target.clone()).norm() <= self.tolerance {
+            return Ok(self.max_iterations);
+        }
+        Err(IkError::NotConverged)
+    }
+}
+
+/// FABRIK (forward and backward reaching inverse kinematics) on an `Rkd` chain.
+///
+/// Each iteration moves the joint positions `p` of the chain onto the target
+/// and back onto the root, keeping the distances between them. The joints
+/// are then turned from the root towards the tip, each one fitting the
+/// positions after it onto the new ones in the least squares sense, which
+/// keeps single-axis joints from stalling on positions they cannot reach.
+pub struct Fabrik<T> {
+    pub max_iterations: usize,
+    pub tolerance: T,
+}
+
+impl<T: na::RealField> Default for Fabrik<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Fabrik<T> {
+    pub fn new() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: T::default_epsilon().sqrt(),
+        }
+    }
+
+    /// Same contract as `Ccd::solve`.
+    pub fn solve(
+        &self,
+        rkd: &mut Rkd<T>,
+        tip: usize,
+        target: &na::Vector3<T>,
+    ) -> Result<usize, IkError> {
+        let joints = reachable_joints(rkd, tip, target)?;
+        let n = joints.len();
+
+        for iteration in 0..self.max_iterations {
+            if (rkd.links[tip].p.clone() - target.clone()).norm() <= self.tolerance {
+                return Ok(iteration);
+            }
+
+            // positions of the joints followed by the tip
+            let mut p = Vec::<na::Vector3<T>, 256>::new();
+            let mut axes = Vec::<na::Vector3<T>, 256>::new();
+            for &j in joints.iter() {
+                p.push(rkd.links[j].p.clone()).unwrap();
+                axes.push(rkd.links[j].r_quat.transform_vector(&rkd.links[j].a))
+                    .unwrap();
+            }
+            p.push(rkd.links[tip].p.clone()).unwrap();
+            let mut len = Vec::<T, 256>::new();
+            for k in 0..n {
+                len.push((p[k + 1].clone() - p[k].clone()).norm()).unwrap();
+            }
+
+            let mut goal = p.clone();
+            goal[n] = target.clone();
+            for k in (0..n).rev() {
+                goal[k] = Self::towards(&goal[k + 1], &goal[k], len[k].clone());
+            }
+            goal[0] = p[0].clone();
+            for k in 0..n {
+                goal[k + 1] = Self::towards(&goal[k], &goal[k + 1], len[k].clone());
+            }
+
+            // turning a joint moves every position and axis after it
+            for k in 0..n {
+                // least squares fit of all the following positions onto their goals
+                let mut sin = T::zero();
+                let mut cos = T::zero();
+                for m in k + 1..=n {
+                    let (from, to) = in_plane(
+                        &axes[k],
+                        &(p[m].clone() - p[k].clone()),
+                        &(goal[m].clone() - p[k].clone()),
+                    );
+                    sin += axes[k].dot(&from.cross(&to));
+                    cos += from.dot(&to);
+                }
+                let delta = sin.atan2(cos);
+                let turned = turn(&mut rkd.links[joints[k]], delta);
+                let rot = na::UnitQuaternion::from_axis_angle(
+                    &na::Unit::new_unchecked(axes[k].clone()),
+                    turned,
+                );
+                for m in k + 1..=n {
+                    p[m] = p[k].clone() + rot.transform_vector(&(p[m].clone() - p[k].clone()));
+                    if m < n {
+                        axes[m] = rot.transform_vector(&axes[m]);
+                    }
+                }
+            }
+            rkd.update_kinematic_relationship();
+        }
+        if (rkd.links[tip].p.clone() - target.clone()).norm() <= self.tolerance {
+            return Ok(self.max_iterations);
+        }
+        Err(IkError::NotConverged)
+    }
+
+    /// Point at `len` from `anchor` on the way to `p`.
+    fn towards(anchor: &na::Vector3<T>, p: &na::Vector3<T>, len: T) -> na::Vector3<T> {
+        let d = p.clone() - anchor.clone();
+        let norm = d.norm();
+        if norm == T::zero() {
+            return p.clone();
+        }
+        anchor.clone() + d * (len / norm)
+    }
+}
+
+#[cfg(test)]
+mod test_chain {
+    use crate::ik::{Ccd, Fabrik, IkError};
+    use crate::link::Link;
+    use crate::rkd::Rkd;
+    use approx::assert_relative_eq;
+
+    /// Snake of `n` unit links turning alternately about z and y, with a tip link.
+    fn snake(n: usize) -> Rkd<f64> {
+        let mut rkd = Rkd::new();
+        rkd.links.push(Link::new()).unwrap();
+        for i in 1..=n + 1 {
+            let mut l = Link::new();
+            l.id = i as u8;
+            l.parent = (i - 1) as u8;
+            l.a = if i % 2 == 1 {
+                na::Vector3::z_axis()
+            } else {
+                na::Vector3::y_axis()
+            };
+            if i > 1 {
+                l.b = na::Vector3::new(1.0, 0.0, 0.0);
+            }
+            rkd.links.push(l).unwrap();
+            rkd.links[i - 1].children.push(i as u8).unwrap();
+        }
+        rkd.update_kinematic_relationship();
+        rkd
+    }
+
+    fn target(rkd: &mut Rkd<f64>, q: &[f64]) -> na::Vector3<f64> {
+        let saved: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.q).collect();
+        for (i, q) in q.iter().enumerate() {
+            rkd.links[i + 1].q = *q;
+        }
+        rkd.update_kinematic_relationship();
+        let p = rkd.links[rkd.links.len() - 1].p;
+        for (l, q) in rkd.links.iter_mut().zip(saved.iter()) {
+            l.q = *q;
+        }
+        rkd.update_kinematic_relationship();
+        p
+    }
+
+    #[test]
+    fn ccd() {
+        let mut rkd = snake(8);
+        let tip = 9;
+        let goal = target(&mut rkd, &[0.3, -0.4, 0.5, 0.2, -0.6, 0.4, 0.1, -0.3]);
+        let mut ccd = Ccd::new();
+        ccd.max_iterations = 500;
+        ccd.tolerance = 1.0e-6;
+        assert!(ccd.solve(&mut rkd, tip, &goal).is_ok());
+        assert!((rkd.links[tip].p - goal).norm() <= 1.0e-6);
+    }
+
+    #[test]
+    fn fabrik() {
+        let mut rkd = snake(8);
+        let tip = 9;
+        let goal = target(&mut rkd, &[0.3, -0.4, 0.5, 0.2, -0.6, 0.4, 0.1, -0.3]);
+        let mut fabrik = Fabrik::new();
+        fabrik.max_iterations = 500;
+        fabrik.tolerance = 1.0e-6;
+        assert!(fabrik.solve(&mut rkd, tip, &goal).is_ok());
+        assert!((rkd.links[tip].p - goal).norm() <= 1.0e-6);
+    }
+
+    #[test]
+    fn joint_limits() {
+        let mut rkd = snake(8);
+        let tip = 9;
+        let goal = target(&mut rkd, &[0.3, -0.4, 0.5, 0.2, -0.6, 0.4, 0.1, -0.3]);
+        for l in rkd.links.iter_mut().skip(1) {
+            l.q_min = -0.5;
+            l.q_max = 0.5;
+        }
+        let mut ccd = Ccd::new();
+        ccd.max_iterations = 1000;
+        ccd.tolerance = 1.0e-6;
+        assert!(ccd.solve(&mut rkd, tip, &goal).is_ok());
+        assert!(rkd.links.iter().all(|l| l.q.abs() <= 0.5));
+
+        let mut rkd = snake(8);
+        let mut fabrik = Fabrik::new();
+        fabrik.max_iterations = 3;
+        for l in rkd.links.iter_mut().skip(1) {
+            l.q_min = -0.1;
+            l.q_max = 0.1;
+        }
+        let goal = na::Vector3::new(0.0, 0.0, 5.0);
+        assert_eq!(
+            fabrik.solve(&mut rkd, tip, &goal),
+            Err(IkError::NotConverged)
+        );
+        assert!(rkd.links.iter().all(|l| l.q.abs() <= 0.1));
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut rkd = snake(8);
+        let tip = 9;
+        let goal = na::Vector3::new(0.0, 8.5, 0.0);
+        assert_eq!(Ccd::new().solve(&mut rkd, tip, &goal), Err(IkError::TooFar));
+        assert_eq!(
+            Fabrik::new().solve(&mut rkd, tip, &goal),
+            Err(IkError::TooFar)
+        );
+        assert!(rkd.links.iter().all(|l| l.q == 0.0));
+        assert_eq!(rkd.links[tip].p, na::Vector3::new(8.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn short_chain() {
+        // a single joint swinging the tip round a circle
+        let mut rkd = snake(1);
+        let goal = na::Vector3::new(0.6, 0.8, 0.0);
+        assert!(Ccd::new().solve(&mut rkd, 2, &goal).is_ok());
+        assert_relative_eq!(rkd.links[2].p, goal, epsilon = 1.0e-6);
+        let mut rkd = snake(1);
+        assert!(Fabrik::new().solve(&mut rkd, 2, &goal).is_ok());
+        assert_relative_eq!(rkd.links[2].p, goal, epsilon = 1.0e-6);
+
+        // no joint moves the origin of a tip next to the root
+        assert_eq!(
+            Ccd::new().solve(&mut rkd, 1, &goal),
+            Err(IkError::UnsupportedGeometry)
+        );
+        assert_eq!(
+            Fabrik::new().solve(&mut rkd, 0, &goal),
+            Err(IkError::UnsupportedGeometry)
+        );
+    }
+}
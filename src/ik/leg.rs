@@ -0,0 +1,421 @@
+use super::{check_joint_limits, wrap_angle, IkError};
+use crate::link::Link;
+use crate::planar::Planar2R;
+
+/// Which way the knee of a leg points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Knee {
+    /// Knee ahead of the hip-foot line, knee angle positive.
+    Forward,
+    /// Knee behind the hip-foot line, knee angle negative.
+    Backward,
+}
+
+/// Kinematics of a 3-DoF quadruped leg: hip abduction, hip pitch and knee.
+///
+/// The hip frame has +x forward, +y left and +z up, with the abduction axis
+/// at the origin.
+/// - `q[0]`: abduction about +x.
+/// - `q[1]`: hip pitch about +y, zero with the thigh pointing down.
+/// - `q[2]`: knee pitch about +y, zero with the leg stretched.
+///
+/// `l_hip` is the offset of the hip pitch axis along +y, negative for right legs.
+pub struct IK3dQuadrupedLeg<T> {
+    pub l_hip: T,
+    pub l_thigh: T,
+    pub l_calf: T,
+    pub knee: Knee,
+}
+
+impl<T: na::RealField> Default for IK3dQuadrupedLeg<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> IK3dQuadrupedLeg<T> {
+    pub fn new() -> Self {
+        Self {
+            l_hip: T::zero(),
+            l_thigh: T::zero(),
+            l_calf: T::zero(),
+            knee: Knee::Backward,
+        }
+    }
+
+    /// Foot position in the hip frame.
+    pub fn forward(&self, q: &[T; 3]) -> na::Vector3<T> {
+        let (s0, c0) = q[0].clone().sin_cos();
+        let (x, z) = self.sagittal(q);
+        let h = self.l_hip.clone();
+        na::Vector3::new(
+            x,
+            c0.clone() * h.clone() - s0.clone() * z.clone(),
+            s0 * h + c0 * z,
+        )
+    }
+
+    /// Foot position in the leg plane before abduction.
+    fn sagittal(&self, q: &[T; 3]) -> (T, T) {
+        let (s1, c1) = q[1].clone().sin_cos();
+        let (s12, c12) = (q[1].clone() + q[2].clone()).sin_cos();
+        (
+            -self.l_thigh.clone() * s1 - self.l_calf.clone() * s12,
+            -self.l_thigh.clone() * c1 - self.l_calf.clone() * c12,
+        )
+    }
+
+    /// Joint angles placing the foot at `p` in the hip frame, with the knee
+    /// pointing as `knee` says.
+    pub fn solve(&self, p: &na::Vector3<T>) -> Result<[T; 3], IkError> {
+        let h = self.l_hip.clone();
+        let z_sq = p.y.clone() * p.y.clone() + p.z.clone() * p.z.clone() - h.clone() * h.clone();
+        if z_sq < T::zero() {
+            return Err(IkError::TooNear);
+        }
+        // the foot stays below the abduction axis
+        let z = -z_sq.sqrt();
+        let q0 = p.z.clone().atan2(p.y.clone()) - z.clone().atan2(h);
+
+        let plane = Planar2R {
+            l1: self.l_thigh.clone(),
+            l2: self.l_calf.clone(),
+        };
+        let branches = plane.solve(&na::Vector2::new(-z, -p.x.clone()))?;
+        let [q1, q2] = match self.knee {
+            Knee::Forward => branches[0].clone(),
+            Knee::Backward => branches[1].clone(),
+        };
+        Ok([wrap_angle(q0), q1, q2])
+    }
+
+    /// Jacobian of the foot position with respect to `q`.
+    pub fn jacobian(&self, q: &[T; 3]) -> na::Matrix3<T> {
+        let (s0, c0) = q[0].clone().sin_cos();
+        let (s12, c12) = (q[1].clone() + q[2].clone()).sin_cos();
+        let (x, z) = self.sagittal(q);
+        let p = self.forward(q);
+        let l2 = self.l_calf.clone();
+        na::Matrix3::new(
+            T::zero(),
+            z,
+            -l2.clone() * c12,
+            -p.z.clone(),
+            s0.clone() * x.clone(),
+            -s0 * l2.clone() * s12.clone(),
+            p.y.clone(),
+            -c0.clone() * x,
+            c0 * l2 * s12,
+        )
+    }
+
+    /// Joint torques exerting `force` at the foot, `τ = Jᵀ f`.
+    pub fn joint_torques(&self, q: &[T; 3], force: &na::Vector3<T>) -> na::Vector3<T> {
+        self.jacobian(q).transpose() * force
+    }
+}
+
+/// Kinematics of a 6-DoF biped leg: hip yaw, roll and pitch, knee, ankle
+/// pitch and roll.
+///
+/// The pelvis frame has +x forward, +y left and +z up. The three hip axes
+/// meet at `hip`, and the two ankle axes at the origin of the foot frame.
+/// - `q[0]`: hip yaw about +z.
+/// - `q[1]`: hip roll about +x.
+/// - `q[2]`: hip pitch about +y.
+/// - `q[3]`: knee pitch about +y, zero with the leg stretched, positive when bent.
+/// - `q[4]`: ankle pitch about +y.
+/// - `q[5]`: ankle roll about +x.
+///
+/// At zero the thigh and the calf point straight down.
+pub struct IK6dBipedLeg<T> {
+    pub hip: na::Vector3<T>,
+    pub l_thigh: T,
+    pub l_calf: T,
+}
+
+impl<T: na::RealField> Default for IK6dBipedLeg<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> IK6dBipedLeg<T> {
+    pub fn new() -> Self {
+        Self {
+            hip: na::Vector3::zeros(),
+            l_thigh: T::zero(),
+            l_calf: T::zero(),
+        }
+    }
+
+    /// Foot pose in the pelvis frame.
+    pub fn forward(&self, q: &[T; 6]) -> na::Isometry3<T> {
+        let rot = |axis: na::Unit<na::Vector3<T>>, angle: &T| {
+            na::Isometry3::from_parts(
+                na::Translation3::identity(),
+                na::UnitQuaternion::from_axis_angle(&axis, angle.clone()),
+            )
+        };
+        na::Isometry3::translation(self.hip.x.clone(), self.hip.y.clone(), self.hip.z.clone())
+            * rot(na::Vector3::z_axis(), &q[0])
+            * rot(na::Vector3::x_axis(), &q[1])
+            * rot(na::Vector3::y_axis(), &q[2])
+            * na::Isometry3::translation(T::zero(), T::zero(), -self.l_thigh.clone())
+            * rot(na::Vector3::y_axis(), &q[3])
+            * na::Isometry3::translation(T::zero(), T::zero(), -self.l_calf.clone())
+            * rot(na::Vector3::y_axis(), &q[4])
+            * rot(na::Vector3::x_axis(), &q[5])
+    }
+
+    /// Joint angles placing the foot at `foot` in the pelvis frame.
+    ///
+    /// A target up to a relative `sqrt(ε)` beyond the stretched leg is taken
+    /// as reachable, with the knee set to zero.
+    pub fn solve(&self, foot: &na::Isometry3<T>) -> Result<[T; 6], IkError> {
+        let a = self.l_thigh.clone();
+        let b = self.l_calf.clone();
+        if a <= T::zero() || b <= T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        let tol = T::default_epsilon().sqrt();
+
+        // hip seen from the ankle in the foot frame
+        let r = foot
+            .inverse_transform_point(&self.hip.clone().into())
+            .coords;
+        let c = r.norm();
+        if c > (a.clone() + b.clone()) * (T::one() + tol.clone()) {
+            return Err(IkError::TooFar);
+        }
+        if c < (a.clone() - b.clone()).abs() * (T::one() - tol) {
+            return Err(IkError::TooNear);
+        }
+        // four times the triangle area (Heron), clamped against rounding on the workspace boundary
+        let area4 = ((a.clone() + b.clone() + c.clone())
+            * (-a.clone() + b.clone() + c.clone())
+            * (a.clone() - b.clone() + c.clone())
+            * (a.clone() + b.clone() - c.clone()))
+        .max(T::zero())
+        .sqrt();
+        let q3 = area4.atan2(c.clone() * c - a.clone() * a.clone() - b.clone() * b.clone());
+        let (s3, c3) = q3.clone().sin_cos();
+
+        let mut q5 = r.y.clone().atan2(r.z.clone());
+        if q5 > T::frac_pi_2() {
+            q5 -= T::pi();
+        } else if q5 < -T::frac_pi_2() {
+            q5 += T::pi();
+        }
+        let w = r.y.clone().hypot(r.z.clone());
+        let w = if r.z < T::zero() { -w } else { w };
+        let q4 = -r.x.clone().atan2(w) - (a.clone() * s3).atan2(b + a * c3);
+
+        // Rz(q0) Rx(q1) Ry(q2)
+        let hip = foot.rotation.clone()
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), -q5.clone())
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), -q3.clone() - q4.clone());
+        let m = hip.to_rotation_matrix().into_inner();
+        let q0 = (-m[(0, 1)].clone()).atan2(m[(1, 1)].clone());
+        let (s0, c0) = q0.clone().sin_cos();
+        let q1 = m[(2, 1)]
+            .clone()
+            .atan2(-m[(0, 1)].clone() * s0 + m[(1, 1)].clone() * c0);
+        let q2 = (-m[(2, 0)].clone()).atan2(m[(2, 2)].clone());
+
+        Ok([q0, q1, q2, q3, wrap_angle(q4), q5])
+    }
+
+    /// `solve` followed by a check of the angles against the limits of
+    /// `links`, one link per joint in the order of `q`.
+    pub fn solve_within_limits(
+        &self,
+        foot: &na::Isometry3<T>,
+        links: &[Link<T>],
+    ) -> Result<[T; 6], IkError> {
+        let q = self.solve(foot)?;
+        check_joint_limits(&q, links)?;
+        Ok(q)
+    }
+}
+
+#[cfg(test)]
+mod test_leg {
+    use crate::ik::{IK3dQuadrupedLeg, IK6dBipedLeg, IkError, Knee};
+    use crate::link::Link;
+    use approx::assert_relative_eq;
+
+    fn leg() -> IK3dQuadrupedLeg<f64> {
+        let mut leg = IK3dQuadrupedLeg::new();
+        leg.l_hip = 0.08;
+        leg.l_thigh = 0.2;
+        leg.l_calf = 0.2;
+        leg
+    }
+
+    #[test]
+    fn quadruped_round_trip() {
+        let mut leg = leg();
+        for q in [[0.1, 0.8, -1.5], [-0.3, 0.2, -0.4], [0.2, -0.5, 1.2]] {
+            leg.knee = if q[2] > 0.0 {
+                Knee::Forward
+            } else {
+                Knee::Backward
+            };
+            let p = leg.forward(&q);
+            let ans = leg.solve(&p).unwrap();
+            for i in 0..3 {
+                assert_relative_eq!(ans[i], q[i], epsilon = 1.0e-9);
+            }
+        }
+
+        // right leg
+        leg.l_hip = -0.08;
+        leg.knee = Knee::Backward;
+        let q = [-0.2, 0.7, -1.4];
+        let ans = leg.solve(&leg.forward(&q)).unwrap();
+        for i in 0..3 {
+            assert_relative_eq!(ans[i], q[i], epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn quadruped_standing() {
+        let mut leg = leg();
+        let p = na::Vector3::new(0.0, 0.08, -0.2 * 2.0_f64.sqrt());
+        let ans = leg.solve(&p).unwrap();
+        let quarter = core::f64::consts::FRAC_PI_4;
+        assert_relative_eq!(ans[0], 0.0, epsilon = 1.0e-9);
+        assert_relative_eq!(ans[1], quarter, epsilon = 1.0e-9);
+        assert_relative_eq!(ans[2], -2.0 * quarter, epsilon = 1.0e-9);
+
+        leg.knee = Knee::Forward;
+        let ans = leg.solve(&p).unwrap();
+        assert_relative_eq!(ans[1], -quarter, epsilon = 1.0e-9);
+        assert_relative_eq!(ans[2], 2.0 * quarter, epsilon = 1.0e-9);
+    }
+
+    #[test]
+    fn quadruped_unreachable() {
+        let leg = leg();
+        assert_eq!(
+            leg.solve(&na::Vector3::new(0.0, 0.08, -0.5)),
+            Err(IkError::TooFar)
+        );
+        assert_eq!(
+            leg.solve(&na::Vector3::new(0.0, 0.01, 0.0)),
+            Err(IkError::TooNear)
+        );
+    }
+
+    #[test]
+    fn quadruped_jacobian() {
+        let leg = leg();
+        let q = [0.3, 0.7, -1.1];
+        let jac = leg.jacobian(&q);
+        let h = 1.0e-6;
+        for k in 0..3 {
+            let mut plus = q;
+            let mut minus = q;
+            plus[k] += h;
+            minus[k] -= h;
+            let diff = (leg.forward(&plus) - leg.forward(&minus)) / (2.0 * h);
+            assert_relative_eq!(jac.column(k).into_owned(), diff, epsilon = 1.0e-6);
+        }
+
+        // virtual work: τ·dq = f·dp
+        let force = na::Vector3::new(1.0, -2.0, 30.0);
+        let dq = na::Vector3::new(0.01, -0.02, 0.03);
+        assert_relative_eq!(
+            leg.joint_torques(&q, &force).dot(&dq),
+            force.dot(&(jac * dq)),
+            epsilon = 1.0e-9
+        );
+    }
+
+    fn biped() -> IK6dBipedLeg<f64> {
+        let mut leg = IK6dBipedLeg::new();
+        leg.hip = na::Vector3::new(0.0, 0.1, -0.05);
+        leg.l_thigh = 0.3;
+        leg.l_calf = 0.28;
+        leg
+    }
+
+    #[test]
+    fn biped_round_trip() {
+        let leg = biped();
+        for q in [
+            [0.1, -0.2, -0.4, 0.8, -0.3, 0.15],
+            [-0.5, 0.3, -1.2, 1.9, 0.2, -0.4],
+            [0.0, 0.0, 0.3, 0.0, -0.3, 0.0],
+        ] {
+            let foot = leg.forward(&q);
+            let ans = leg.solve(&foot).unwrap();
+            for i in 0..6 {
+                assert_relative_eq!(ans[i], q[i], epsilon = 1.0e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn biped_stretched() {
+        let leg = biped();
+        let q = [0.2, 0.1, -0.3, 0.0, 0.1, -0.1];
+        let foot = leg.forward(&q);
+
+        // a hair beyond the stretched leg is clamped to it
+        let hip = na::Point3::from(leg.hip);
+        let dir = foot.translation.vector - leg.hip;
+        let mut far = foot;
+        far.translation.vector = hip.coords + dir * (1.0 + 1.0e-12);
+        let ans = leg.solve(&far).unwrap();
+        assert_eq!(ans[3], 0.0);
+        let t = leg.forward(&ans);
+        assert_relative_eq!(
+            t.translation.vector,
+            foot.translation.vector,
+            epsilon = 1.0e-9
+        );
+        assert_relative_eq!(t.rotation.angle_to(&foot.rotation), 0.0, epsilon = 1.0e-6);
+
+        far.translation.vector = hip.coords + dir * 1.01;
+        assert_eq!(leg.solve(&far), Err(IkError::TooFar));
+    }
+
+    #[test]
+    fn biped_nearly_stretched() {
+        let leg = biped();
+        // the knee angle is only as good as the hip–ankle distance allows
+        for q3 in [1.0e-2, 1.0e-4, 1.0e-6] {
+            let q = [0.2, 0.1, -0.3, q3, 0.1, -0.1];
+            let foot = leg.forward(&q);
+            let ans = leg.solve(&foot).unwrap();
+            assert_relative_eq!(ans[3], q3, epsilon = 1.0e-9);
+            let t = leg.forward(&ans);
+            assert_relative_eq!(
+                t.translation.vector,
+                foot.translation.vector,
+                epsilon = 1.0e-12
+            );
+            assert_relative_eq!(t.rotation.angle_to(&foot.rotation), 0.0, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn biped_joint_limits() {
+        let leg = biped();
+        let q = [0.1, -0.2, -0.4, 0.8, -0.3, 0.15];
+        let foot = leg.forward(&q);
+        let mut links: [Link<f64>; 6] = Default::default();
+        assert!(leg.solve_within_limits(&foot, &links).is_ok());
+
+        links[3].q_min = 0.0;
+        links[3].q_max = 2.5;
+        assert!(leg.solve_within_limits(&foot, &links).is_ok());
+        links[3].q_max = 0.5;
+        assert_eq!(
+            leg.solve_within_limits(&foot, &links),
+            Err(IkError::JointLimit)
+        );
+    }
+}
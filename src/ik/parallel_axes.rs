@@ -0,0 +1,341 @@
+use heapless::Vec;
+
+use super::{wrap_angle, IkError};
+use crate::dh::DHParam;
+
+/// Singular configurations a solution of `IK6dParallelAxes` is at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Singularity {
+    /// The wrist lies on the cylinder swept by the lateral offset around the base axis.
+    pub shoulder: bool,
+    /// The upper arm and forearm are aligned.
+    pub elbow: bool,
+    /// The first and last wrist axes are parallel.
+    pub wrist: bool,
+}
+
+/// One branch returned by `IK6dParallelAxes::solve`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelAxesSolution<T> {
+    pub q: [T; 6],
+    pub singularity: Singularity,
+}
+
+/// Closed-form inverse kinematics of a UR-style 6-DoF arm whose shoulder,
+/// elbow and first wrist axes are parallel.
+///
+/// `dh` is the DH table of the arm in the convention of `DH::from_dh_param`:
+/// - `alfa` is `[0, π/2, 0, 0, π/2, -π/2]`.
+/// - `a[0]`, `a[1]`, `a[4]` and `a[5]` are zero.
+/// - `a[2]` and `a[3]` are the upper arm and forearm lengths.
+/// - `d[0]` is the base height, `d[1] + d[2] + d[3]` the lateral offset of the
+///   wrist, `d[4]` and `d[5]` the wrist offsets.
+///
+/// The `theta` offsets are free.
+pub struct IK6dParallelAxes<T> {
+    pub dh: [DHParam<T>; 6],
+}
+
+impl<T: na::RealField> IK6dParallelAxes<T> {
+    pub fn new(dh: [DHParam<T>; 6]) -> Self {
+        Self { dh }
+    }
+
+    fn check_geometry(&self) -> Result<(), IkError> {
+        let tol = T::default_epsilon().sqrt();
+        let half_pi = T::frac_pi_2();
+        let alfa = [
+            T::zero(),
+            half_pi.clone(),
+            T::zero(),
+            T::zero(),
+            half_pi.clone(),
+            -half_pi,
+        ];
+        for (param, alfa) in self.dh.iter().zip(alfa.iter()) {
+            if (param.alfa.clone() - alfa.clone()).abs() > tol {
+                return Err(IkError::UnsupportedGeometry);
+            }
+        }
+        for value in [&self.dh[0].a, &self.dh[1].a, &self.dh[4].a, &self.dh[5].a] {
+            if value.clone().abs() > tol {
+                return Err(IkError::UnsupportedGeometry);
+            }
+        }
+        if self.dh[2].a == T::zero() || self.dh[3].a == T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        Ok(())
+    }
+
+    /// All joint solutions reaching `target`, the pose of the last DH frame.
+    ///
+    /// Solutions are ordered by shoulder, wrist and elbow branch and wrapped
+    /// into (-π, π]. Branches whose elbow cannot reach are left out.
+    /// At a wrist singularity only `q[1] + q[2] + q[3]` and `q[5]` together
+    /// are determined, and `q[5]` is set to zero.
+    pub fn solve(
+        &self,
+        target: &na::Isometry3<T>,
+    ) -> Result<Vec<ParallelAxesSolution<T>, 8>, IkError> {
+        self.check_geometry()?;
+        let tol = T::default_epsilon().sqrt();
+
+        let rot = target.rotation.clone().to_rotation_matrix().into_inner();
+        let z6 = rot.column(2).into_owned();
+        let p5 = target.translation.vector.clone() - z6.clone() * self.dh[5].d.clone();
+
+        let d234 = self.dh[1].d.clone() + self.dh[2].d.clone() + self.dh[3].d.clone();
+        let rho_sq = p5.x.clone() * p5.x.clone() + p5.y.clone() * p5.y.clone();
+        let lateral_sq = rho_sq.clone() - d234.clone() * d234.clone();
+        if lateral_sq < T::zero() {
+            return Err(IkError::TooNear);
+        }
+        let lateral = lateral_sq.sqrt();
+        let shoulder_singular = lateral <= tol.clone() * rho_sq.sqrt();
+
+        let a2 = self.dh[2].a.clone();
+        let a3 = self.dh[3].a.clone();
+        let two = T::one() + T::one();
+        let t45 = self.dh[4].to_isometry(T::zero()).translation.vector;
+
+        let mut ans = Vec::new();
+        for shoulder in [T::one(), -T::one()] {
+            let th1 = p5.y.clone().atan2(p5.x.clone())
+                - (-d234.clone()).atan2(shoulder * lateral.clone());
+            let (s1, c1) = th1.clone().sin_cos();
+
+            let c5 = (s1.clone() * z6.x.clone() - c1.clone() * z6.y.clone())
+                .max(-T::one())
+                .min(T::one());
+            let s5_abs =
+                (c1.clone() * z6.x.clone() + s1.clone() * z6.y.clone()).hypot(z6.z.clone());
+            // row 1 of the target rotation seen from frame 1
+            let ry0 = -s1.clone() * rot[(0, 0)].clone() + c1.clone() * rot[(1, 0)].clone();
+            let ry1 = -s1.clone() * rot[(0, 1)].clone() + c1.clone() * rot[(1, 1)].clone();
+
+            for wrist in [T::one(), -T::one()] {
+                let th5 = (wrist.clone() * s5_abs.clone()).atan2(c5.clone());
+                let wrist_singular = s5_abs <= tol;
+                let th6 = if wrist_singular {
+                    self.dh[5].theta.clone()
+                } else {
+                    (wrist.clone() * ry1.clone()).atan2(-wrist.clone() * ry0.clone())
+                };
+
+                let r01 = self.dh[0].to_isometry(th1.clone() - self.dh[0].theta.clone());
+                let r46 = self.dh[4].to_isometry(th5.clone() - self.dh[4].theta.clone())
+                    * self.dh[5].to_isometry(th6.clone() - self.dh[5].theta.clone());
+                let r04 = target.rotation.clone() * r46.rotation.inverse();
+                let rx1 = na::UnitQuaternion::from_axis_angle(
+                    &na::Vector3::x_axis(),
+                    self.dh[1].alfa.clone(),
+                );
+                // Rz(th2 + th3 + th4)
+                let m = (rx1.inverse() * r01.rotation.inverse() * r04.clone())
+                    .to_rotation_matrix()
+                    .into_inner();
+                let th234 = m[(1, 0)].clone().atan2(m[(0, 0)].clone());
+
+                let p4 = p5.clone() - r04.transform_vector(&t45);
+                let v =
+                    rx1.inverse_transform_vector(&r01.inverse_transform_point(&p4.into()).coords);
+                let x = v.x.clone();
+                let y = v.y.clone();
+                let c3 = (x.clone() * x.clone() + y.clone() * y.clone()
+                    - a2.clone() * a2.clone()
+                    - a3.clone() * a3.clone())
+                    / (two.clone() * a2.clone() * a3.clone());
+                if c3 > T::one() + tol.clone() || c3 < -T::one() - tol.clone() {
+                    continue;
+                }
+                let c3 = c3.max(-T::one()).min(T::one());
+                let s3_abs = (T::one() - c3.clone() * c3.clone()).sqrt();
+                let elbow_singular = T::one() - c3.clone().abs() <= tol;
+
+                for elbow in [T::one(), -T::one()] {
+                    let th3 = (elbow * s3_abs.clone()).atan2(c3.clone());
+                    let (s3, c3) = th3.clone().sin_cos();
+                    let th2 = y.clone().atan2(x.clone())
+                        - (a3.clone() * s3).atan2(a2.clone() + a3.clone() * c3);
+                    let th4 = th234.clone() - th2.clone() - th3.clone();
+
+                    let th = [th1.clone(), th2, th3, th4, th5.clone(), th6.clone()];
+                    let mut q = [
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                    ];
+                    for i in 0..6 {
+                        q[i] = wrap_angle(th[i].clone() - self.dh[i].theta.clone());
+                    }
+                    ans.push(ParallelAxesSolution {
+                        q,
+                        singularity: Singularity {
+                            shoulder: shoulder_singular,
+                            elbow: elbow_singular,
+                            wrist: wrist_singular,
+                        },
+                    })
+                    .unwrap();
+                }
+            }
+        }
+
+        if ans.is_empty() {
+            return Err(IkError::TooFar);
+        }
+        Ok(ans)
+    }
+}
+
+#[cfg(test)]
+mod test_parallel_axes {
+    use crate::dh::{forward, DHParam};
+    use crate::ik::{wrap_angle, IK6dParallelAxes, IkError};
+    use approx::assert_relative_eq;
+    use core::f64::consts::FRAC_PI_2;
+
+    fn ur5e() -> IK6dParallelAxes<f64> {
+        IK6dParallelAxes::new([
+            DHParam::new(0.0, 0.0, 0.1625, 0.0),
+            DHParam::new(0.0, FRAC_PI_2, 0.0, 0.0),
+            DHParam::new(-0.425, 0.0, 0.0, 0.0),
+            DHParam::new(-0.3922, 0.0, 0.1333, 0.0),
+            DHParam::new(0.0, FRAC_PI_2, 0.0997, 0.0),
+            DHParam::new(0.0, -FRAC_PI_2, 0.0996, 0.0),
+        ])
+    }
+
+    /// `ur5e` with nonzero `theta` offsets.
+    fn offset() -> IK6dParallelAxes<f64> {
+        let mut ik = ur5e();
+        for (dh, theta) in ik.dh.iter_mut().zip([0.1, -0.2, 0.3, 0.15, -0.25, 0.4]) {
+            dh.theta = theta;
+        }
+        ik
+    }
+
+    #[test]
+    fn round_trip() {
+        let ik = ur5e();
+        let qs = [
+            [0.3, -1.2, 1.5, -0.6, -0.7, 0.8],
+            [-2.0, -0.4, -1.1, -2.5, 1.2, 3.0],
+            [1.0, -2.2, 2.0, 0.1, 2.3, -0.2],
+        ];
+        for q in qs {
+            let target = forward(&ik.dh, &q);
+            let ans = ik.solve(&target).unwrap();
+            assert_eq!(ans.len(), 8);
+
+            let mut found = false;
+            for sol in ans.iter() {
+                assert_eq!(sol.singularity, Default::default());
+                let t = forward(&ik.dh, &sol.q);
+                assert_relative_eq!(
+                    t.translation.vector,
+                    target.translation.vector,
+                    epsilon = 1.0e-9
+                );
+                assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+                found |= sol
+                    .q
+                    .iter()
+                    .zip(q.iter())
+                    .all(|(a, b)| wrap_angle(a - b).abs() < 1.0e-9);
+            }
+            assert!(found);
+        }
+    }
+
+    #[test]
+    fn round_trip_offsets() {
+        let ik = offset();
+        let qs = [
+            [0.3, -1.2, 1.5, -0.6, -0.7, 0.8],
+            [-2.0, -0.4, -1.1, -2.5, 1.2, 3.0],
+            [1.0, -2.2, 2.0, 0.1, 2.3, -0.2],
+        ];
+        for q in qs {
+            let target = forward(&ik.dh, &q);
+            let ans = ik.solve(&target).unwrap();
+            // the offsets only shift the joint values of the same arm
+            let th: [f64; 6] = core::array::from_fn(|i| q[i] + ik.dh[i].theta);
+            let plain = ur5e();
+            assert_eq!(
+                ans.len(),
+                plain.solve(&forward(&plain.dh, &th)).unwrap().len()
+            );
+
+            let mut found = false;
+            for sol in ans.iter() {
+                let t = forward(&ik.dh, &sol.q);
+                assert_relative_eq!(
+                    t.translation.vector,
+                    target.translation.vector,
+                    epsilon = 1.0e-9
+                );
+                assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+                found |= sol
+                    .q
+                    .iter()
+                    .zip(q.iter())
+                    .all(|(a, b)| wrap_angle(a - b).abs() < 1.0e-9);
+            }
+            assert!(found);
+        }
+    }
+
+    #[test]
+    fn singular_flags() {
+        let ik = ur5e();
+        let q = [0.3, -1.2, 1.5, -0.6, 0.0, 0.8];
+        let target = forward(&ik.dh, &q);
+        let ans = ik.solve(&target).unwrap();
+        assert!(ans.iter().any(|sol| sol.singularity.wrist));
+        for sol in ans.iter().filter(|sol| sol.singularity.wrist) {
+            let t = forward(&ik.dh, &sol.q);
+            assert_relative_eq!(
+                t.translation.vector,
+                target.translation.vector,
+                epsilon = 1.0e-9
+            );
+            assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+        }
+
+        let q = [0.3, -1.2, 0.0, -0.6, 0.9, 0.8];
+        let target = forward(&ik.dh, &q);
+        let ans = ik.solve(&target).unwrap();
+        assert!(ans.iter().any(|sol| sol.singularity.elbow));
+
+        // q[5] is zero at the wrist singularity also with theta offsets
+        let ik = offset();
+        let q = [0.3, -1.2, 1.5, -0.6, 0.25, 0.8];
+        let target = forward(&ik.dh, &q);
+        let ans = ik.solve(&target).unwrap();
+        assert!(ans.iter().any(|sol| sol.singularity.wrist));
+        for sol in ans.iter().filter(|sol| sol.singularity.wrist) {
+            assert_eq!(sol.q[5], 0.0);
+            let t = forward(&ik.dh, &sol.q);
+            assert_relative_eq!(
+                t.translation.vector,
+                target.translation.vector,
+                epsilon = 1.0e-9
+            );
+            assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn unreachable() {
+        let ik = ur5e();
+        let target = na::Isometry3::translation(2.0, 0.0, 0.5);
+        assert_eq!(ik.solve(&target), Err(IkError::TooFar));
+        let target = na::Isometry3::translation(0.0, 0.0, 0.5);
+        assert_eq!(ik.solve(&target), Err(IkError::TooNear));
+    }
+}
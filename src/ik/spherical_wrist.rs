@@ -0,0 +1,265 @@
+use heapless::Vec;
+
+use super::{wrap_angle, IkError};
+use crate::dh::DHParam;
+
+/// Closed-form inverse kinematics of a 6-DoF arm with a spherical wrist (Pieper).
+///
+/// `dh` is the DH table of the arm in the convention of `DH::from_dh_param`,
+/// laid out like the PUMA 560:
+/// - `alfa` is `[0, -π/2, 0, -π/2, π/2, -π/2]`.
+/// - `a[0]`, `a[4]`, `a[5]` and `d[4]` are zero, so the last three axes meet
+///   at the wrist center.
+/// - `d[5]` is the flange offset from the wrist center.
+///
+/// The shoulder offset `a[1]`, the lateral offset `d[1] + d[2]` and the
+/// `theta` offsets are free.
+pub struct IK6dSphericalWrist<T> {
+    pub dh: [DHParam<T>; 6],
+}
+
+impl<T: na::RealField> IK6dSphericalWrist<T> {
+    pub fn new(dh: [DHParam<T>; 6]) -> Self {
+        Self { dh }
+    }
+
+    fn check_geometry(&self) -> Result<(), IkError> {
+        let tol = T::default_epsilon().sqrt();
+        let half_pi = T::frac_pi_2();
+        let alfa = [
+            T::zero(),
+            -half_pi.clone(),
+            T::zero(),
+            -half_pi.clone(),
+            half_pi.clone(),
+            -half_pi,
+        ];
+        for (param, alfa) in self.dh.iter().zip(alfa.iter()) {
+            if (param.alfa.clone() - alfa.clone()).abs() > tol {
+                return Err(IkError::UnsupportedGeometry);
+            }
+        }
+        for value in [&self.dh[0].a, &self.dh[4].a, &self.dh[5].a, &self.dh[4].d] {
+            if value.clone().abs() > tol {
+                return Err(IkError::UnsupportedGeometry);
+            }
+        }
+        if self.dh[2].a == T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        Ok(())
+    }
+
+    /// All joint solutions reaching `target`, the pose of the last DH frame.
+    ///
+    /// Solutions are ordered by shoulder, elbow and wrist branch and wrapped
+    /// into (-π, π]. A singular wrist only yields one solution per arm branch,
+    /// with `q[3]` set to zero.
+    pub fn solve(&self, target: &na::Isometry3<T>) -> Result<Vec<[T; 6], 8>, IkError> {
+        self.check_geometry()?;
+
+        let wrist = target.translation.vector.clone()
+            - target.rotation.transform_vector(&na::Vector3::z()) * self.dh[5].d.clone();
+        let px = wrist.x.clone();
+        let py = wrist.y.clone();
+        let pz = wrist.z.clone() - self.dh[0].d.clone();
+
+        let a1 = self.dh[1].a.clone();
+        let a2 = self.dh[2].a.clone();
+        let a3 = self.dh[3].a.clone();
+        let d23 = self.dh[1].d.clone() + self.dh[2].d.clone();
+        let d4 = self.dh[3].d.clone();
+        let two = T::one() + T::one();
+
+        let rho_sq = px.clone() * px.clone() + py.clone() * py.clone() - d23.clone() * d23.clone();
+        if rho_sq < T::zero() {
+            return Err(IkError::TooNear);
+        }
+
+        let mut ans = Vec::new();
+        let mut err = IkError::TooFar;
+        for shoulder in [T::one(), -T::one()] {
+            let th1 =
+                py.clone().atan2(px.clone()) - d23.clone().atan2(shoulder * rho_sq.clone().sqrt());
+            let (s1, c1) = th1.clone().sin_cos();
+            let r = c1 * px.clone() + s1 * py.clone() - a1.clone();
+
+            let k = (r.clone() * r.clone() + pz.clone() * pz.clone()
+                - a2.clone() * a2.clone()
+                - a3.clone() * a3.clone()
+                - d4.clone() * d4.clone())
+                / (two.clone() * a2.clone());
+            let rho3_sq = a3.clone() * a3.clone() + d4.clone() * d4.clone();
+            let disc = rho3_sq - k.clone() * k.clone();
+            if disc < T::zero() {
+                err = if k > T::zero() {
+                    IkError::TooFar
+                } else {
+                    IkError::TooNear
+                };
+                continue;
+            }
+
+            for elbow in [T::one(), -T::one()] {
+                let th3 =
+                    (elbow * disc.clone().sqrt()).atan2(k.clone()) - d4.clone().atan2(a3.clone());
+                let (s3, c3) = th3.clone().sin_cos();
+                let x2 = a2.clone() + a3.clone() * c3.clone() - d4.clone() * s3.clone();
+                let y2 = a3.clone() * s3 + d4.clone() * c3;
+                let th2 = (-pz.clone()).atan2(r.clone()) - y2.atan2(x2);
+
+                let r03 = (self.dh[0].to_isometry(th1.clone() - self.dh[0].theta.clone())
+                    * self.dh[1].to_isometry(th2.clone() - self.dh[1].theta.clone())
+                    * self.dh[2].to_isometry(th3.clone() - self.dh[2].theta.clone()))
+                .rotation;
+                let rx3 = na::UnitQuaternion::from_axis_angle(
+                    &na::Vector3::x_axis(),
+                    self.dh[3].alfa.clone(),
+                );
+                // Rz(th4) Ry(-th5) Rz(th6)
+                let m = (rx3.inverse() * r03.inverse() * target.rotation.clone())
+                    .to_rotation_matrix()
+                    .into_inner();
+
+                let sb = m[(0, 2)].clone().hypot(m[(1, 2)].clone());
+                let singular = sb <= T::default_epsilon().sqrt();
+                for wrist in [T::one(), -T::one()] {
+                    let (th4, th5, th6) = if singular {
+                        // q[3] = 0, joint 6 takes the whole turn about the aligned axes
+                        let th4 = self.dh[3].theta.clone();
+                        if m[(2, 2)] > T::zero() {
+                            (
+                                th4.clone(),
+                                T::zero(),
+                                m[(1, 0)].clone().atan2(m[(0, 0)].clone()) - th4,
+                            )
+                        } else {
+                            (
+                                th4.clone(),
+                                T::pi(),
+                                m[(0, 1)].clone().atan2(-m[(0, 0)].clone()) + th4,
+                            )
+                        }
+                    } else {
+                        (
+                            (wrist.clone() * m[(1, 2)].clone())
+                                .atan2(wrist.clone() * m[(0, 2)].clone()),
+                            -(wrist.clone() * sb.clone()).atan2(m[(2, 2)].clone()),
+                            (wrist.clone() * m[(2, 1)].clone())
+                                .atan2(-wrist.clone() * m[(2, 0)].clone()),
+                        )
+                    };
+
+                    let th = [th1.clone(), th2.clone(), th3.clone(), th4, th5, th6];
+                    let mut q = [
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                        T::zero(),
+                    ];
+                    for i in 0..6 {
+                        q[i] = wrap_angle(th[i].clone() - self.dh[i].theta.clone());
+                    }
+                    ans.push(q).unwrap();
+
+                    if singular {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if ans.is_empty() {
+            return Err(err);
+        }
+        Ok(ans)
+    }
+}
+
+#[cfg(test)]
+mod test_spherical_wrist {
+    use crate::dh::{forward, DHParam};
+    use crate::ik::{wrap_angle, IK6dSphericalWrist, IkError};
+    use approx::assert_relative_eq;
+    use core::f64::consts::{FRAC_PI_2, PI};
+
+    fn puma() -> IK6dSphericalWrist<f64> {
+        IK6dSphericalWrist::new([
+            DHParam::new(0.0, 0.0, 0.67, 0.0),
+            DHParam::new(0.05, -FRAC_PI_2, 0.15, -FRAC_PI_2),
+            DHParam::new(0.4318, 0.0, -0.02, 0.0),
+            DHParam::new(0.0203, -FRAC_PI_2, 0.4318, 0.0),
+            DHParam::new(0.0, FRAC_PI_2, 0.0, 0.0),
+            DHParam::new(0.0, -FRAC_PI_2, 0.056, 0.3),
+        ])
+    }
+
+    #[test]
+    fn round_trip() {
+        let ik = puma();
+        // the second pose is out of reach for the other shoulder branch
+        let qs = [
+            ([0.3, -0.4, 0.5, 0.6, -0.7, 0.8], 8),
+            ([-2.0, 0.9, -1.1, -2.5, 1.2, 3.0], 4),
+            ([1.0, 0.2, 2.0, 0.1, 0.3, -0.2], 8),
+        ];
+        for (q, n) in qs {
+            let target = forward(&ik.dh, &q);
+            let ans = ik.solve(&target).unwrap();
+            assert_eq!(ans.len(), n);
+
+            let mut found = false;
+            for sol in ans.iter() {
+                let t = forward(&ik.dh, sol);
+                assert_relative_eq!(
+                    t.translation.vector,
+                    target.translation.vector,
+                    epsilon = 1.0e-9
+                );
+                assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+                found |= sol
+                    .iter()
+                    .zip(q.iter())
+                    .all(|(a, b)| wrap_angle(a - b).abs() < 1.0e-9);
+            }
+            assert!(found);
+        }
+    }
+
+    #[test]
+    fn singular_wrist() {
+        let mut ik = puma();
+        ik.dh[3].theta = 0.25;
+        for q4 in [0.0, PI] {
+            let q = [0.3, -0.4, 0.5, 0.6, q4, 0.8];
+            let target = forward(&ik.dh, &q);
+            let ans = ik.solve(&target).unwrap();
+            // only the arm branch of q has the wrist singular
+            assert_eq!(ans.len(), 7);
+            assert_relative_eq!(wrap_angle(ans[0][4] - q4), 0.0, epsilon = 1.0e-9);
+            assert_eq!(ans[0][3], 0.0);
+            for sol in ans.iter() {
+                let t = forward(&ik.dh, sol);
+                assert_relative_eq!(
+                    t.translation.vector,
+                    target.translation.vector,
+                    epsilon = 1.0e-9
+                );
+                assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut ik = puma();
+        let target = na::Isometry3::translation(3.0, 0.0, 0.5);
+        assert_eq!(ik.solve(&target), Err(IkError::TooFar));
+
+        ik.dh[1].alfa = FRAC_PI_2;
+        let target = forward(&ik.dh, &[0.0; 6]);
+        assert_eq!(ik.solve(&target), Err(IkError::UnsupportedGeometry));
+    }
+}
@@ -0,0 +1,466 @@
+use heapless::Vec;
+
+use super::{wrap_angle, IkError};
+
+/// Branch of `IK7dSRS`, given by the signs of `q[1]`, `q[3]` and `q[5]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SRSBranch {
+    pub shoulder_negative: bool,
+    pub elbow_negative: bool,
+    pub wrist_negative: bool,
+}
+
+impl SRSBranch {
+    /// The branch `q` is in.
+    pub fn of<T: na::RealField>(q: &[T; 7]) -> Self {
+        Self {
+            shoulder_negative: q[1] < T::zero(),
+            elbow_negative: q[3] < T::zero(),
+            wrist_negative: q[5] < T::zero(),
+        }
+    }
+}
+
+/// Closed-form inverse kinematics of a 7-DoF spherical-shoulder,
+/// revolute-elbow, spherical-wrist arm, parameterized by the swivel angle.
+///
+/// The arm is `Tz(d_bs) Rz(q0) Ry(q1) Rz(q2) Tz(d_se) Ry(q3) Tz(d_ew) Rz(q4) Ry(q5) Rz(q6) Tz(d_wf)`,
+/// so it points straight up along +z when all angles are zero.
+///
+/// The swivel angle `psi` is the rotation of the elbow about the shoulder-wrist
+/// line, measured from the reference plane where `q2` is zero.
+/// Like `ref_theta` of `IK4dTriangle`, it picks one elbow position out of the circle
+/// of solutions. The reference plane is undefined when the wrist is straight
+/// above or below the shoulder, and `q0` is then taken as zero.
+pub struct IK7dSRS<T> {
+    /// base to shoulder
+    pub d_bs: T,
+    /// shoulder to elbow
+    pub d_se: T,
+    /// elbow to wrist
+    pub d_ew: T,
+    /// wrist to flange
+    pub d_wf: T,
+    pub q_min: [T; 7],
+    pub q_max: [T; 7],
+}
+
+/// `R = a sin(psi) + b cos(psi) + c` for the shoulder and wrist rotations.
+struct SwivelCoefficients<T: na::RealField> {
+    q3: T,
+    shoulder: [na::Matrix3<T>; 3],
+    wrist: [na::Matrix3<T>; 3],
+}
+
+impl<T: na::RealField> Default for IK7dSRS<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> IK7dSRS<T> {
+    pub fn new() -> Self {
+        Self {
+            d_bs: T::zero(),
+            d_se: T::zero(),
+            d_ew: T::zero(),
+            d_wf: T::zero(),
+            q_min: [
+                -T::pi(),
+                -T::pi(),
+                -T::pi(),
+                -T::pi(),
+                -T::pi(),
+                -T::pi(),
+                -T::pi(),
+            ],
+            q_max: [
+                T::pi(),
+                T::pi(),
+                T::pi(),
+                T::pi(),
+                T::pi(),
+                T::pi(),
+                T::pi(),
+            ],
+        }
+    }
+
+    pub fn forward(&self, q: &[T; 7]) -> na::Isometry3<T> {
+        let tz = |d: &T| na::Isometry3::translation(T::zero(), T::zero(), d.clone());
+        let rz = |q: &T| na::Isometry3::rotation(na::Vector3::z() * q.clone());
+        let ry = |q: &T| na::Isometry3::rotation(na::Vector3::y() * q.clone());
+        tz(&self.d_bs)
+            * rz(&q[0])
+            * ry(&q[1])
+            * rz(&q[2])
+            * tz(&self.d_se)
+            * ry(&q[3])
+            * tz(&self.d_ew)
+            * rz(&q[4])
+            * ry(&q[5])
+            * rz(&q[6])
+            * tz(&self.d_wf)
+    }
+
+    /// Swivel angle of the joint state `q`.
+    pub fn swivel_angle(&self, q: &[T; 7]) -> T {
+        let shoulder = na::Vector3::z() * self.d_bs.clone();
+        let wrist = self.forward(q) * na::Point3::new(T::zero(), T::zero(), -self.d_wf.clone());
+        let x_sw = wrist.coords - shoulder;
+        let r03 = self.reference_shoulder(&x_sw, q[3].clone());
+        let r03_actual = na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), q[0].clone())
+            * na::Rotation3::from_axis_angle(&na::Vector3::y_axis(), q[1].clone())
+            * na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), q[2].clone());
+
+        let u = x_sw.normalize();
+        let perp = |v: na::Vector3<T>| v.clone() - u.clone() * u.dot(&v);
+        let e0 = perp(r03 * na::Vector3::z());
+        let e = perp(r03_actual * na::Vector3::z());
+        u.dot(&e0.cross(&e)).atan2(e0.dot(&e))
+    }
+
+    /// Shoulder rotation with `q2` zero placing the wrist at `x_sw` from the
+    /// shoulder, for the elbow angle `q3`.
+    fn reference_shoulder(&self, x_sw: &na::Vector3<T>, q3: T) -> na::Rotation3<T> {
+        let (s3, c3) = q3.sin_cos();
+        let vx = self.d_ew.clone() * s3;
+        let vz = self.d_se.clone() + self.d_ew.clone() * c3;
+        let q0 = x_sw.y.clone().atan2(x_sw.x.clone());
+        let q1 = x_sw.x.clone().hypot(x_sw.y.clone()).atan2(x_sw.z.clone()) - vx.atan2(vz);
+        na::Rotation3::from_axis_angle(&na::Vector3::z_axis(), q0)
+            * na::Rotation3::from_axis_angle(&na::Vector3::y_axis(), q1)
+    }
+
+    fn coefficients(
+        &self,
+        target: &na::Isometry3<T>,
+        branch: &SRSBranch,
+    ) -> Result<SwivelCoefficients<T>, IkError> {
+        if self.d_se <= T::zero() || self.d_ew <= T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        let rot = target.rotation.clone().to_rotation_matrix();
+        let shoulder = na::Vector3::z() * self.d_bs.clone();
+        let wrist = target.translation.vector.clone()
+            - rot.matrix().column(2) * self.d_wf.clone()
+            - shoulder;
+        let l = wrist.norm();
+
+        let two = T::one() + T::one();
+        let c3 = (l.clone() * l.clone()
+            - self.d_se.clone() * self.d_se.clone()
+            - self.d_ew.clone() * self.d_ew.clone())
+            / (two * self.d_se.clone() * self.d_ew.clone());
+        let tol = T::default_epsilon().sqrt();
+        if c3 > T::one() + tol.clone() {
+            return Err(IkError::TooFar);
+        }
+        if c3 < -T::one() - tol || l == T::zero() {
+            return Err(IkError::TooNear);
+        }
+        let c3 = c3.max(-T::one()).min(T::one());
+        let mut q3 = (T::one() - c3.clone() * c3.clone()).sqrt().atan2(c3);
+        if branch.elbow_negative {
+            q3 = -q3;
+        }
+
+        let r03 = self.reference_shoulder(&wrist, q3.clone()).into_inner();
+        let u = (wrist / l).cross_matrix();
+        let a_s = u.clone() * r03.clone();
+        let b_s = -u.clone() * u.clone() * r03.clone();
+        let c_s = r03 - b_s.clone();
+
+        let r34 = na::Rotation3::from_axis_angle(&na::Vector3::y_axis(), q3.clone()).into_inner();
+        let wrist_of = |m: &na::Matrix3<T>| r34.transpose() * m.transpose() * rot.matrix();
+        Ok(SwivelCoefficients {
+            q3,
+            shoulder: [a_s.clone(), b_s.clone(), c_s.clone()],
+            wrist: [wrist_of(&a_s), wrist_of(&b_s), wrist_of(&c_s)],
+        })
+    }
+
+    /// Joint angles reaching `target`, the flange pose, with the swivel angle `psi`.
+    ///
+    /// Joint limits are not checked, see `feasible_swivel_intervals`.
+    pub fn solve(
+        &self,
+        target: &na::Isometry3<T>,
+        psi: T,
+        branch: &SRSBranch,
+    ) -> Result<[T; 7], IkError> {
+        let coef = self.coefficients(target, branch)?;
+        Ok(Self::angles(&coef, psi, branch))
+    }
+
+    fn angles(coef: &SwivelCoefficients<T>, psi: T, branch: &SRSBranch) -> [T; 7] {
+        let (s, c) = psi.sin_cos();
+        let at = |m: &[na::Matrix3<T>; 3]| {
+            m[0].clone() * s.clone() + m[1].clone() * c.clone() + m[2].clone()
+        };
+        let (q0, q1, q2) = Self::zyz(&at(&coef.shoulder), branch.shoulder_negative);
+        let (q4, q5, q6) = Self::zyz(&at(&coef.wrist), branch.wrist_negative);
+        [q0, q1, q2, coef.q3.clone(), q4, q5, q6]
+    }
+
+    /// `Rz(a) Ry(b) Rz(c)` angles of `m`, with `b` negative when `negative` is set.
+    fn zyz(m: &na::Matrix3<T>, negative: bool) -> (T, T, T) {
+        let sign = if negative { -T::one() } else { T::one() };
+        let sb = m[(0, 2)].clone().hypot(m[(1, 2)].clone());
+        if sb <= T::default_epsilon().sqrt() {
+            // only a + c (or a - c) is defined
+            return if m[(2, 2)] > T::zero() {
+                (
+                    T::zero(),
+                    T::zero(),
+                    m[(1, 0)].clone().atan2(m[(0, 0)].clone()),
+                )
+            } else {
+                (
+                    T::zero(),
+                    sign * T::pi(),
+                    m[(0, 1)].clone().atan2(-m[(0, 0)].clone()),
+                )
+            };
+        }
+        (
+            (sign.clone() * m[(1, 2)].clone()).atan2(sign.clone() * m[(0, 2)].clone()),
+            (sign.clone() * sb).atan2(m[(2, 2)].clone()),
+            (sign.clone() * m[(2, 1)].clone()).atan2(-sign * m[(2, 0)].clone()),
+        )
+    }
+
+    fn within_limits(&self, q: &[T; 7]) -> bool {
+        q.iter()
+            .zip(self.q_min.iter().zip(self.q_max.iter()))
+            .all(|(q, (min, max))| q >= min && q <= max)
+    }
+
+    /// Swivel angle intervals in [-π, π] over which `solve` stays within
+    /// `q_min` and `q_max`, in increasing order.
+    ///
+    /// Infinite limits leave a joint free, NaN limits give `JointLimit`.
+    pub fn feasible_swivel_intervals(
+        &self,
+        target: &na::Isometry3<T>,
+        branch: &SRSBranch,
+    ) -> Result<Vec<(T, T), 32>, IkError> {
+        // a NaN limit is the only one not equal to itself
+        let limits = self.q_min.iter().chain(self.q_max.iter());
+        if limits.clone().any(|q| q.clone() != q.clone()) {
+            return Err(IkError::JointLimit);
+        }
+        let coef = self.coefficients(target, branch)?;
+        let mut intervals = Vec::<(T, T), 32>::new();
+        if coef.q3 < self.q_min[3] || coef.q3 > self.q_max[3] {
+            return Ok(intervals);
+        }
+
+        // the limits can only be crossed where a joint hits one of them or wraps at ±π
+        let mut candidates = Vec::<T, 40>::new();
+        candidates.push(-T::pi()).unwrap();
+        candidates.push(T::pi()).unwrap();
+        let element = |m: &[na::Matrix3<T>; 3], i: usize, j: usize, sign: T| {
+            [
+                m[0][(i, j)].clone() * sign.clone(),
+                m[1][(i, j)].clone() * sign.clone(),
+                m[2][(i, j)].clone() * sign,
+            ]
+        };
+        let sign = |negative: bool| if negative { -T::one() } else { T::one() };
+        let tan_type = [
+            (&coef.shoulder, (1, 2), (0, 2), branch.shoulder_negative, 0),
+            (&coef.shoulder, (2, 1), (2, 0), branch.shoulder_negative, 2),
+            (&coef.wrist, (1, 2), (0, 2), branch.wrist_negative, 4),
+            (&coef.wrist, (2, 1), (2, 0), branch.wrist_negative, 6),
+        ];
+        for (m, n, d, negative, joint) in tan_type {
+            let num = element(m, n.0, n.1, sign(negative));
+            // q2 and q6 use -m[(2, 0)] as their denominator
+            let den_sign = if joint % 4 == 2 {
+                -sign(negative)
+            } else {
+                sign(negative)
+            };
+            let den = element(m, d.0, d.1, den_sign);
+            Self::push_roots(&mut candidates, &num, T::zero());
+            for limit in [self.q_min[joint].clone(), self.q_max[joint].clone()] {
+                let (s, c) = limit.sin_cos();
+                let eq = [
+                    num[0].clone() * c.clone() - den[0].clone() * s.clone(),
+                    num[1].clone() * c.clone() - den[1].clone() * s.clone(),
+                    num[2].clone() * c - den[2].clone() * s,
+                ];
+                Self::push_roots(&mut candidates, &eq, T::zero());
+            }
+        }
+        for (m, joint) in [(&coef.shoulder, 1), (&coef.wrist, 5)] {
+            let cos = element(m, 2, 2, T::one());
+            for limit in [self.q_min[joint].clone(), self.q_max[joint].clone()] {
+                Self::push_roots(&mut candidates, &cos, limit.cos());
+            }
+        }
+        candidates.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+        for pair in candidates.windows(2) {
+            let (lo, hi) = (pair[0].clone(), pair[1].clone());
+            if hi.clone() - lo.clone() <= T::default_epsilon() {
+                continue;
+            }
+            let mid = (lo.clone() + hi.clone()) / (T::one() + T::one());
+            if !self.within_limits(&Self::angles(&coef, mid, branch)) {
+                continue;
+            }
+            match intervals.last_mut() {
+                Some(last) if last.1 == lo => last.1 = hi,
+                _ => intervals.push((lo, hi)).unwrap(),
+            }
+        }
+        Ok(intervals)
+    }
+
+    /// Push the roots in (-π, π] of `eq[0] sin(psi) + eq[1] cos(psi) + eq[2] = value`.
+    fn push_roots(candidates: &mut Vec<T, 40>, eq: &[T; 3], value: T) {
+        let rho = eq[0].clone().hypot(eq[1].clone());
+        let rhs = value - eq[2].clone();
+        // also skips non-finite coefficients, so the candidates can be sorted
+        if !(rho.is_finite() && rhs.is_finite())
+            || rho <= T::default_epsilon()
+            || rhs.clone().abs() > rho
+        {
+            return;
+        }
+        let phi = eq[0].clone().atan2(eq[1].clone());
+        let delta = (rhs / rho).acos();
+        candidates
+            .push(wrap_angle(phi.clone() + delta.clone()))
+            .unwrap();
+        candidates.push(wrap_angle(phi - delta)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test_srs {
+    use crate::ik::{wrap_angle, IK7dSRS, IkError, SRSBranch};
+    use approx::assert_relative_eq;
+
+    fn iiwa() -> IK7dSRS<f64> {
+        let mut ik = IK7dSRS::new();
+        ik.d_bs = 0.36;
+        ik.d_se = 0.42;
+        ik.d_ew = 0.4;
+        ik.d_wf = 0.126;
+        let deg = core::f64::consts::PI / 180.0;
+        ik.q_max = [
+            170.0 * deg,
+            120.0 * deg,
+            170.0 * deg,
+            120.0 * deg,
+            170.0 * deg,
+            120.0 * deg,
+            175.0 * deg,
+        ];
+        for i in 0..7 {
+            ik.q_min[i] = -ik.q_max[i];
+        }
+        ik
+    }
+
+    #[test]
+    fn round_trip() {
+        let ik = iiwa();
+        let qs = [
+            [0.3, 0.5, -0.4, 1.2, 0.6, -0.7, 0.8],
+            [-2.0, -0.9, 1.1, -0.5, -2.5, 1.2, 3.0],
+            [1.0, 0.2, 2.0, 1.8, 0.1, 0.3, -0.2],
+        ];
+        for q in qs {
+            let target = ik.forward(&q);
+            let psi = ik.swivel_angle(&q);
+            let ans = ik.solve(&target, psi, &SRSBranch::of(&q)).unwrap();
+            for i in 0..7 {
+                assert_relative_eq!(wrap_angle(ans[i] - q[i]), 0.0, epsilon = 1.0e-9);
+            }
+
+            for k in 0..12 {
+                let psi = -3.0 + 0.5 * k as f64;
+                let ans = ik.solve(&target, psi, &SRSBranch::of(&q)).unwrap();
+                let t = ik.forward(&ans);
+                assert_relative_eq!(
+                    t.translation.vector,
+                    target.translation.vector,
+                    epsilon = 1.0e-9
+                );
+                assert_relative_eq!(t.rotation.angle_to(&target.rotation), 0.0, epsilon = 1.0e-6);
+                assert_relative_eq!(ik.swivel_angle(&ans), psi, epsilon = 1.0e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn swivel_intervals() {
+        let ik = iiwa();
+        let q = [0.3, 0.5, -0.4, 1.2, 0.6, -0.7, 0.8];
+        let target = ik.forward(&q);
+        let branch = SRSBranch::of(&q);
+        let intervals = ik.feasible_swivel_intervals(&target, &branch).unwrap();
+        assert!(!intervals.is_empty());
+        let psi = ik.swivel_angle(&q);
+        assert!(intervals.iter().any(|(lo, hi)| *lo <= psi && psi <= *hi));
+
+        for k in 0..360 {
+            let psi = -core::f64::consts::PI + (k as f64 + 0.5) * core::f64::consts::PI / 180.0;
+            let ans = ik.solve(&target, psi, &branch).unwrap();
+            let feasible = (0..7).all(|i| ik.q_min[i] <= ans[i] && ans[i] <= ik.q_max[i]);
+            let inside = intervals.iter().any(|(lo, hi)| *lo <= psi && psi <= *hi);
+            let near_boundary = intervals
+                .iter()
+                .any(|(lo, hi)| (psi - lo).abs() < 1.0e-6 || (psi - hi).abs() < 1.0e-6);
+            if !near_boundary {
+                assert_eq!(feasible, inside);
+            }
+        }
+    }
+
+    #[test]
+    fn elbow_limit() {
+        let mut ik = iiwa();
+        let q = [0.3, 0.5, -0.4, 1.2, 0.6, -0.7, 0.8];
+        let target = ik.forward(&q);
+        ik.q_max[3] = 1.0;
+        let intervals = ik
+            .feasible_swivel_intervals(&target, &SRSBranch::of(&q))
+            .unwrap();
+        assert!(intervals.is_empty());
+    }
+
+    #[test]
+    fn non_finite_limits() {
+        let mut ik = iiwa();
+        let q = [0.3, 0.5, -0.4, 1.2, 0.6, -0.7, 0.8];
+        let target = ik.forward(&q);
+        let branch = SRSBranch::of(&q);
+        ik.q_max[2] = f64::NAN;
+        assert_eq!(
+            ik.feasible_swivel_intervals(&target, &branch),
+            Err(IkError::JointLimit)
+        );
+
+        // an infinite limit leaves the joint free
+        ik.q_min[2] = f64::NEG_INFINITY;
+        ik.q_max[2] = f64::INFINITY;
+        let intervals = ik.feasible_swivel_intervals(&target, &branch).unwrap();
+        let psi = ik.swivel_angle(&q);
+        assert!(intervals.iter().any(|(lo, hi)| *lo <= psi && psi <= *hi));
+    }
+
+    #[test]
+    fn unreachable() {
+        let ik = iiwa();
+        let target = na::Isometry3::translation(2.0, 0.0, 0.5);
+        assert_eq!(
+            ik.solve(&target, 0.0, &SRSBranch::default()),
+            Err(IkError::TooFar)
+        );
+    }
+}
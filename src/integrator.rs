@@ -0,0 +1,262 @@
+use heapless::Vec;
+
+use crate::ik::wrap_angle;
+use crate::rkd::Rkd;
+
+/// Scheme used by `Simulator::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    /// Position and velocity both from the state at the start of the step.
+    ExplicitEuler,
+    /// Velocity first, then position from the new velocity.
+    SemiImplicitEuler,
+    /// Classic fourth order Runge–Kutta.
+    Rk4,
+}
+
+/// What happens to a joint outside `q_min..q_max`.
+///
+/// Joints with `q_min == q_max` or `continuous` set have no limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitHandling<T> {
+    /// Limits are ignored.
+    None,
+    /// The angle is clamped after each step and the velocity into the limit dropped.
+    Clamp,
+    /// A spring-damper torque pushes the joint back while it is beyond a limit.
+    Spring { stiffness: T, damping: T },
+}
+
+/// Steps the joint state `q`, `dqdt` of an `Rkd` in time with its forward dynamics.
+pub struct Simulator<T> {
+    pub integrator: Integrator,
+    pub limits: LimitHandling<T>,
+}
+
+impl<T: na::RealField> Default for Simulator<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Simulator<T> {
+    pub fn new() -> Self {
+        Self {
+            integrator: Integrator::SemiImplicitEuler,
+            limits: LimitHandling::None,
+        }
+    }
+
+    /// Advance `rkd` by `dt` under the joint torques `tau`, indexed by link id.
+    ///
+    /// `ddqddt` is left at the acceleration of the start of the step, and the
+    /// kinematics are updated to the new state.
+    pub fn step(&self, rkd: &mut Rkd<T>, tau: &[T], dt: T) {
+        let n = rkd.links.len();
+        let q0: Vec<T, 256> = rkd.links.iter().map(|l| l.q.clone()).collect();
+        let dq0: Vec<T, 256> = rkd.links.iter().map(|l| l.dqdt.clone()).collect();
+        let ddq0 = self.acceleration(rkd, tau, &q0, &dq0);
+
+        let mut q = q0.clone();
+        let mut dq = dq0.clone();
+        match self.integrator {
+            Integrator::ExplicitEuler => {
+                for i in 0..n {
+                    q[i] += dq0[i].clone() * dt.clone();
+                    dq[i] += ddq0[i].clone() * dt.clone();
+                }
+            }
+            Integrator::SemiImplicitEuler => {
+                for i in 0..n {
+                    dq[i] += ddq0[i].clone() * dt.clone();
+                    q[i] += dq[i].clone() * dt.clone();
+                }
+            }
+            Integrator::Rk4 => {
+                let two = T::one() + T::one();
+                let six: T = na::convert(6.0);
+                let half = dt.clone() / two.clone();
+                let shift = |x: &Vec<T, 256>, dx: &Vec<T, 256>, h: &T| -> Vec<T, 256> {
+                    x.iter()
+                        .zip(dx.iter())
+                        .map(|(x, dx)| x.clone() + dx.clone() * h.clone())
+                        .collect()
+                };
+                let (k1q, k1v) = (dq0.clone(), ddq0.clone());
+                let (q2, dq2) = (shift(&q0, &k1q, &half), shift(&dq0, &k1v, &half));
+                let k2v = self.acceleration(rkd, tau, &q2, &dq2);
+                let (q3, dq3) = (shift(&q0, &dq2, &half), shift(&dq0, &k2v, &half));
+                let k3v = self.acceleration(rkd, tau, &q3, &dq3);
+                let (q4, dq4) = (shift(&q0, &dq3, &dt), shift(&dq0, &k3v, &dt));
+                let k4v = self.acceleration(rkd, tau, &q4, &dq4);
+                for i in 0..n {
+                    q[i] += (k1q[i].clone()
+                        + (dq2[i].clone() + dq3[i].clone()) * two.clone()
+                        + dq4[i].clone())
+                        * dt.clone()
+                        / six.clone();
+                    dq[i] += (k1v[i].clone()
+                        + (k2v[i].clone() + k3v[i].clone()) * two.clone()
+                        + k4v[i].clone())
+                        * dt.clone()
+                        / six.clone();
+                }
+            }
+        }
+
+        for (i, link) in rkd.links.iter_mut().enumerate().skip(1) {
+            link.q = q[i].clone();
+            link.dqdt = dq[i].clone();
+            link.ddqddt = ddq0[i].clone();
+            if link.continuous {
+                link.q = wrap_angle(link.q.clone());
+            } else if self.limits == LimitHandling::Clamp && link.q_min != link.q_max {
+                if link.q < link.q_min {
+                    link.q = link.q_min.clone();
+                    link.dqdt = link.dqdt.clone().max(T::zero());
+                } else if link.q > link.q_max {
+                    link.q = link.q_max.clone();
+                    link.dqdt = link.dqdt.clone().min(T::zero());
+                }
+            }
+        }
+        rkd.update_kinematic_relationship();
+    }
+
+    /// Joint accelerations at the state `q`, `dq`, with the limit springs.
+    fn acceleration(&self, rkd: &mut Rkd<T>, tau: &[T], q: &[T], dq: &[T]) -> Vec<T, 256> {
+        let mut total = Vec::<T, 256>::new();
+        for (i, link) in rkd.links.iter_mut().enumerate() {
+            link.q = q[i].clone();
+            link.dqdt = dq[i].clone();
+            let mut t = tau[i].clone();
+            if let LimitHandling::Spring { stiffness, damping } = &self.limits {
+                let limited = !link.continuous && link.q_min != link.q_max;
+                let over = if limited && link.q < link.q_min {
+                    link.q.clone() - link.q_min.clone()
+                } else if limited && link.q > link.q_max {
+                    link.q.clone() - link.q_max.clone()
+                } else {
+                    T::zero()
+                };
+                if over != T::zero() {
+                    t -= stiffness.clone() * over + damping.clone() * link.dqdt.clone();
+                }
+            }
+            total.push(t).unwrap();
+        }
+        rkd.forward_dynamics(&total, None);
+        rkd.links.iter().map(|l| l.ddqddt.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test_integrator {
+    use crate::integrator::{Integrator, LimitHandling, Simulator};
+    use crate::link::Link;
+    use crate::rkd::Rkd;
+    use approx::assert_relative_eq;
+
+    const G: f64 = 9.8;
+
+    /// Point mass on a massless rod of length 1, turning about x.
+    fn pendulum(q: f64) -> Rkd<f64> {
+        let mut rkd = Rkd::new();
+        let mut l0 = Link::new();
+        l0.children.push(1).unwrap();
+        rkd.links.push(l0).unwrap();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -G);
+        let mut l1 = Link::new();
+        l1.id = 1;
+        l1.mass = 1.0;
+        l1.com = na::Vector3::new(0.0, 0.0, -1.0);
+        l1.q = q;
+        rkd.links.push(l1).unwrap();
+        rkd.update_kinematic_relationship();
+        rkd
+    }
+
+    fn energy(rkd: &Rkd<f64>) -> f64 {
+        let l = &rkd.links[1];
+        0.5 * l.dqdt * l.dqdt - G * l.q.cos()
+    }
+
+    fn run(integrator: Integrator, steps: usize) -> f64 {
+        let mut rkd = pendulum(1.0);
+        let e0 = energy(&rkd);
+        let mut sim = Simulator::new();
+        sim.integrator = integrator;
+        for _ in 0..steps {
+            sim.step(&mut rkd, &[0.0, 0.0], 0.01);
+        }
+        energy(&rkd) - e0
+    }
+
+    #[test]
+    fn energy_drift() {
+        let explicit = run(Integrator::ExplicitEuler, 500);
+        let semi = run(Integrator::SemiImplicitEuler, 500);
+        let rk4 = run(Integrator::Rk4, 500);
+        assert!(explicit > 0.1);
+        assert!(semi.abs() < 0.1);
+        assert!(rk4.abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn rk4_small_angle() {
+        // period 2π of the linearized pendulum with g = l
+        let mut rkd = pendulum(1.0e-3);
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -1.0);
+        let mut sim = Simulator::new();
+        sim.integrator = Integrator::Rk4;
+        let steps = 1000;
+        for _ in 0..steps {
+            sim.step(&mut rkd, &[0.0, 0.0], core::f64::consts::TAU / steps as f64);
+        }
+        assert_relative_eq!(rkd.links[1].q, 1.0e-3, epsilon = 1.0e-8);
+    }
+
+    #[test]
+    fn continuous_joint() {
+        let mut rkd = pendulum(3.0);
+        rkd.gravity = na::Vector3::zeros();
+        rkd.links[1].continuous = true;
+        rkd.links[1].dqdt = 2.0;
+        let sim = Simulator::new();
+        for _ in 0..100 {
+            sim.step(&mut rkd, &[0.0, 0.0], 0.01);
+        }
+        assert_relative_eq!(
+            rkd.links[1].q,
+            5.0 - core::f64::consts::TAU,
+            epsilon = 1.0e-9
+        );
+    }
+
+    #[test]
+    fn joint_limits() {
+        let mut sim = Simulator::new();
+        sim.limits = LimitHandling::Clamp;
+        let mut rkd = pendulum(0.5);
+        rkd.links[1].q_min = 0.0;
+        rkd.links[1].q_max = 1.0;
+        for _ in 0..200 {
+            sim.step(&mut rkd, &[0.0, 0.0], 0.01);
+            assert!(rkd.links[1].q >= 0.0 && rkd.links[1].q <= 1.0);
+        }
+
+        sim.limits = LimitHandling::Spring {
+            stiffness: 10000.0,
+            damping: 20.0,
+        };
+        let mut rkd = pendulum(0.5);
+        rkd.links[1].q_min = 0.0;
+        rkd.links[1].q_max = 1.0;
+        let mut lowest: f64 = 0.0;
+        for _ in 0..2000 {
+            sim.step(&mut rkd, &[0.0, 0.0], 0.001);
+            lowest = lowest.min(rkd.links[1].q);
+        }
+        assert!(lowest < 0.0 && lowest > -0.02);
+    }
+}
@@ -14,6 +14,14 @@ pub mod matrix3;
 pub mod quaternion;
 pub mod vector3;
 
+pub mod actuator;
+pub mod delta;
 pub mod ik;
+pub mod integrator;
 pub mod link;
+pub mod mobile;
+pub mod planar;
 pub mod rkd;
+pub mod scara;
+pub mod stewart;
+pub mod zmp;
@@ -2,6 +2,41 @@
 use core::ops::{Add, AddAssign, Mul, Neg};
 use heapless::Vec;
 
+use crate::actuator::Actuator;
+
+/// Coordinates an external `Wrench` is given in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frame {
+    World,
+    Local,
+}
+
+/// External force and moment on a link, applied at `point` given in link coordinates.
+#[derive(Debug, Clone)]
+pub struct Wrench<T> {
+    pub force: na::Vector3<T>,
+    pub moment: na::Vector3<T>,
+    pub point: na::Vector3<T>,
+    pub frame: Frame, // coordinates of force and moment
+}
+
+impl<T: na::RealField> Default for Wrench<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Wrench<T> {
+    pub fn new() -> Self {
+        Self {
+            force: na::Vector3::zeros(),
+            moment: na::Vector3::zeros(),
+            point: na::Vector3::zeros(),
+            frame: Frame::Local,
+        }
+    }
+}
+
 /// 親リンクからつながる位置（回転軸）を原点とする
 #[derive(Debug)]
 pub struct Link<T> {
@@ -16,15 +51,26 @@ pub struct Link<T> {
     pub ddqddt: T,
     pub q_min: T,
     pub q_max: T,
-    pub p: na::Vector3<T>,             // position in world coordinate
+    pub continuous: bool,  // joint without end stops, q wraps into (-π, π]
+    pub p: na::Vector3<T>, // position in world coordinate
     pub r_quat: na::UnitQuaternion<T>, // rotation quat in world coordinate
 
     pub ddpddt: na::Vector3<T>,      // position in local coordinate
     pub ddsddt: na::Vector3<T>,      // com acceleration in local coordinate
     pub w_vec: na::Vector3<T>,       // rotation vec in local coordinate
     pub dwdt_vec: na::Vector3<T>,    // rotation vec in local coordinate
+    pub f_vec: na::Vector3<T>,       // joint reaction force from parent in local coordinate
+    pub n_vec: na::Vector3<T>,       // joint reaction moment about origin in local coordinate
     pub a: na::Unit<na::Vector3<T>>, // joint axis vec relative to parent link
     pub b: na::Vector3<T>,           // joint position relative to parent link
+    pub wrenches: Vec<Wrench<T>, 8>, // external loads on the link
+    pub actuator: Actuator<T>,       // drive of the joint
+}
+
+impl<T: na::RealField> Default for Link<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: na::RealField> Link<T> {
@@ -41,6 +87,7 @@ impl<T: na::RealField> Link<T> {
             ddqddt: T::zero(),
             q_min: T::zero(),
             q_max: T::zero(),
+            continuous: false,
             p: na::Vector3::<T>::zeros(),
             r_quat: na::UnitQuaternion::identity(),
 
@@ -48,9 +95,32 @@ impl<T: na::RealField> Link<T> {
             ddsddt: na::Vector3::<T>::zeros(),
             w_vec: na::Vector3::zeros(),
             dwdt_vec: na::Vector3::zeros(),
+            f_vec: na::Vector3::zeros(),
+            n_vec: na::Vector3::zeros(),
             a: na::Vector3::x_axis(),
             b: na::Vector3::<T>::zeros(),
+            wrenches: Vec::new(),
+            actuator: Actuator::new(),
+        }
+    }
+
+    /// Sum of `wrenches` as a force and a moment about the link origin, in
+    /// local coordinate. World coordinates are converted with `r_quat`.
+    pub fn external_wrench(&self) -> (na::Vector3<T>, na::Vector3<T>) {
+        let mut f = na::Vector3::zeros();
+        let mut n = na::Vector3::zeros();
+        for w in self.wrenches.iter() {
+            let (force, moment) = match w.frame {
+                Frame::Local => (w.force.clone(), w.moment.clone()),
+                Frame::World => (
+                    self.r_quat.inverse_transform_vector(&w.force),
+                    self.r_quat.inverse_transform_vector(&w.moment),
+                ),
+            };
+            n += moment + w.point.cross(&force);
+            f += force;
         }
+        (f, n)
     }
 }
 
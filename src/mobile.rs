@@ -0,0 +1,287 @@
+use crate::rkd::Rkd;
+
+/// Differential-drive base with two wheels on a common axle.
+///
+/// Body twists are `(vx, vy, omega)` in the base frame, +x forward and +y
+/// left. Wheel speeds are `[left, right]` in rad/s, positive rolling forward.
+pub struct DifferentialDrive<T> {
+    pub wheel_radius: T,
+    /// Distance between the two wheels.
+    pub track: T,
+}
+
+impl<T: na::RealField> Default for DifferentialDrive<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> DifferentialDrive<T> {
+    pub fn new() -> Self {
+        Self {
+            wheel_radius: T::zero(),
+            track: T::zero(),
+        }
+    }
+
+    /// Body twist of the wheel speeds `w`.
+    pub fn forward(&self, w: &[T; 2]) -> na::Vector3<T> {
+        let two = T::one() + T::one();
+        let left = self.wheel_radius.clone() * w[0].clone();
+        let right = self.wheel_radius.clone() * w[1].clone();
+        na::Vector3::new(
+            (left.clone() + right.clone()) / two,
+            T::zero(),
+            (right - left) / self.track.clone(),
+        )
+    }
+
+    /// Wheel speeds of the body twist `twist`. The lateral speed cannot be
+    /// produced and is ignored.
+    pub fn inverse(&self, twist: &na::Vector3<T>) -> [T; 2] {
+        let two = T::one() + T::one();
+        let turn = twist.z.clone() * self.track.clone() / two;
+        [
+            (twist.x.clone() - turn.clone()) / self.wheel_radius.clone(),
+            (twist.x.clone() + turn) / self.wheel_radius.clone(),
+        ]
+    }
+}
+
+/// Mecanum base with four wheels whose rollers are at 45°, in an X pattern
+/// seen from above.
+///
+/// Body twists are as in `DifferentialDrive`. Wheel speeds are
+/// `[front left, front right, rear left, rear right]`.
+pub struct Mecanum<T> {
+    pub wheel_radius: T,
+    /// Half the distance between the front and rear axles.
+    pub half_length: T,
+    /// Half the distance between the left and right wheels.
+    pub half_width: T,
+}
+
+impl<T: na::RealField> Default for Mecanum<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Mecanum<T> {
+    pub fn new() -> Self {
+        Self {
+            wheel_radius: T::zero(),
+            half_length: T::zero(),
+            half_width: T::zero(),
+        }
+    }
+
+    /// Body twist of the wheel speeds `w`.
+    pub fn forward(&self, w: &[T; 4]) -> na::Vector3<T> {
+        let k = self.wheel_radius.clone() / na::convert(4.0);
+        let l = self.half_length.clone() + self.half_width.clone();
+        let [fl, fr, rl, rr] = w.clone();
+        na::Vector3::new(
+            k.clone() * (fl.clone() + fr.clone() + rl.clone() + rr.clone()),
+            k.clone() * (-fl.clone() + fr.clone() + rl.clone() - rr.clone()),
+            k * (-fl + fr - rl + rr) / l,
+        )
+    }
+
+    /// Wheel speeds of the body twist `twist`.
+    pub fn inverse(&self, twist: &na::Vector3<T>) -> [T; 4] {
+        let r = self.wheel_radius.clone();
+        let turn = (self.half_length.clone() + self.half_width.clone()) * twist.z.clone();
+        let (vx, vy) = (twist.x.clone(), twist.y.clone());
+        [
+            (vx.clone() - vy.clone() - turn.clone()) / r.clone(),
+            (vx.clone() + vy.clone() + turn.clone()) / r.clone(),
+            (vx.clone() + vy.clone() - turn.clone()) / r.clone(),
+            (vx - vy + turn) / r,
+        ]
+    }
+}
+
+/// Pose after moving from `pose` with the body twist `twist` held for `dt`.
+///
+/// The twist is integrated exactly on SE(2), so a constant twist follows
+/// an arc whatever the step.
+pub fn integrate<T: na::RealField>(
+    pose: &na::Isometry2<T>,
+    twist: &na::Vector3<T>,
+    dt: T,
+) -> na::Isometry2<T> {
+    let dx = twist.x.clone() * dt.clone();
+    let dy = twist.y.clone() * dt.clone();
+    let dth = twist.z.clone() * dt;
+    let (s, c) = dth.clone().sin_cos();
+    // sin(th)/th and (1 - cos(th))/th, by their series near zero
+    let (a, b) = if dth.clone().abs() < T::default_epsilon().sqrt() {
+        let two = T::one() + T::one();
+        (
+            T::one() - dth.clone() * dth.clone() / na::convert(6.0),
+            dth.clone() / two,
+        )
+    } else {
+        (s / dth.clone(), (T::one() - c) / dth.clone())
+    };
+    let step = na::Isometry2::new(
+        na::Vector2::new(
+            a.clone() * dx.clone() - b.clone() * dy.clone(),
+            b * dx + a * dy,
+        ),
+        dth,
+    );
+    pose.clone() * step
+}
+
+/// Base pose on the floor seen as a 3D pose, turning about +z.
+pub fn to_isometry3<T: na::RealField>(pose: &na::Isometry2<T>) -> na::Isometry3<T> {
+    na::Isometry3::new(
+        na::Vector3::new(
+            pose.translation.x.clone(),
+            pose.translation.y.clone(),
+            T::zero(),
+        ),
+        na::Vector3::z() * pose.rotation.angle(),
+    )
+}
+
+/// World pose of link `i` of an arm on a base at `base`.
+///
+/// `rkd.base` is taken as the mounting pose of the arm on the base, so the
+/// arm is placed once and `link_pose` stays in base coordinate.
+pub fn world_link_pose<T: na::RealField>(
+    base: &na::Isometry2<T>,
+    rkd: &Rkd<T>,
+    i: usize,
+) -> na::Isometry3<T> {
+    to_isometry3(base) * rkd.link_pose(i)
+}
+
+#[cfg(test)]
+mod test_mobile {
+    use crate::link::Link;
+    use crate::mobile::{integrate, world_link_pose, DifferentialDrive, Mecanum};
+    use crate::rkd::Rkd;
+    use approx::assert_relative_eq;
+    use core::f64::consts::PI;
+
+    #[test]
+    fn differential_drive() {
+        let mut base = DifferentialDrive::new();
+        base.wheel_radius = 0.1;
+        base.track = 0.5;
+        assert_relative_eq!(
+            base.forward(&[2.0, 2.0]),
+            na::Vector3::new(0.2, 0.0, 0.0),
+            epsilon = 1.0e-12
+        );
+        assert_relative_eq!(
+            base.forward(&[-2.0, 2.0]),
+            na::Vector3::new(0.0, 0.0, 0.8),
+            epsilon = 1.0e-12
+        );
+        let twist = na::Vector3::new(0.3, 0.0, -0.4);
+        assert_relative_eq!(
+            base.forward(&base.inverse(&twist)),
+            twist,
+            epsilon = 1.0e-12
+        );
+    }
+
+    #[test]
+    fn mecanum() {
+        let mut base = Mecanum::new();
+        base.wheel_radius = 0.05;
+        base.half_length = 0.2;
+        base.half_width = 0.15;
+        let twist = na::Vector3::new(0.3, -0.2, 0.5);
+        assert_relative_eq!(
+            base.forward(&base.inverse(&twist)),
+            twist,
+            epsilon = 1.0e-12
+        );
+
+        // sideways to the left
+        let w = base.inverse(&na::Vector3::new(0.0, 1.0, 0.0));
+        assert!(w[0] < 0.0 && w[1] > 0.0 && w[2] > 0.0 && w[3] < 0.0);
+    }
+
+    #[test]
+    fn odometry() {
+        let start = na::Isometry2::new(na::Vector2::new(1.0, 2.0), 0.3);
+        let pose = integrate(&start, &na::Vector3::new(0.5, 0.0, 0.0), 2.0);
+        assert_relative_eq!(
+            pose.translation.vector,
+            na::Vector2::new(1.0 + 0.3_f64.cos(), 2.0 + 0.3_f64.sin()),
+            epsilon = 1.0e-12
+        );
+
+        // a half circle of radius 1 in one step
+        let pose = integrate(
+            &na::Isometry2::identity(),
+            &na::Vector3::new(PI, 0.0, PI),
+            1.0,
+        );
+        assert_relative_eq!(
+            pose.translation.vector,
+            na::Vector2::new(0.0, 2.0),
+            epsilon = 1.0e-12
+        );
+        assert_relative_eq!(pose.rotation.angle().abs(), PI, epsilon = 1.0e-12);
+
+        // one step matches many small ones
+        let twist = na::Vector3::new(0.4, 0.1, 0.7);
+        let mut pose = start;
+        for _ in 0..1000 {
+            pose = integrate(&pose, &twist, 1.0e-3);
+        }
+        let once = integrate(&start, &twist, 1.0);
+        assert_relative_eq!(
+            pose.translation.vector,
+            once.translation.vector,
+            epsilon = 1.0e-9
+        );
+        assert_relative_eq!(
+            pose.rotation.angle(),
+            once.rotation.angle(),
+            epsilon = 1.0e-9
+        );
+    }
+
+    #[test]
+    fn world_pose() {
+        let mut rkd = Rkd::<f64>::new();
+        rkd.links.push(Link::new()).unwrap();
+        let mut l1 = Link::new();
+        l1.id = 1;
+        l1.a = na::Vector3::z_axis();
+        l1.q = 0.5 * PI;
+        rkd.links.push(l1).unwrap();
+        let mut l2 = Link::new();
+        l2.id = 2;
+        l2.parent = 1;
+        l2.b = na::Vector3::new(1.0, 0.0, 0.0);
+        rkd.links.push(l2).unwrap();
+        rkd.links[0].children.push(1).unwrap();
+        rkd.links[1].children.push(2).unwrap();
+        // mounted 0.3 above the base origin
+        rkd.base = na::Isometry3::translation(0.0, 0.0, 0.3);
+        rkd.update_kinematic_relationship();
+
+        let base = na::Isometry2::new(na::Vector2::new(2.0, 0.0), 0.5 * PI);
+        let pose = world_link_pose(&base, &rkd, 2);
+        assert_relative_eq!(
+            pose.translation.vector,
+            na::Vector3::new(1.0, 0.0, 0.3),
+            epsilon = 1.0e-12
+        );
+        assert_relative_eq!(
+            pose.rotation
+                .angle_to(&na::UnitQuaternion::from_euler_angles(0.0, 0.0, PI)),
+            0.0,
+            epsilon = 1.0e-12
+        );
+    }
+}
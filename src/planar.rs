@@ -0,0 +1,285 @@
+use crate::ik::{wrap_angle, IkError};
+
+/// Planar chain of two revolute joints about +z.
+///
+/// `q[0]` is measured from +x and `q[1]` from the first link.
+pub struct Planar2R<T> {
+    pub l1: T,
+    pub l2: T,
+}
+
+impl<T: na::RealField> Default for Planar2R<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Planar2R<T> {
+    pub fn new() -> Self {
+        Self {
+            l1: T::zero(),
+            l2: T::zero(),
+        }
+    }
+
+    pub fn forward(&self, q: &[T; 2]) -> na::Vector2<T> {
+        let q01 = q[0].clone() + q[1].clone();
+        na::Vector2::new(
+            self.l1.clone() * q[0].clone().cos() + self.l2.clone() * q01.clone().cos(),
+            self.l1.clone() * q[0].clone().sin() + self.l2.clone() * q01.sin(),
+        )
+    }
+
+    pub fn jacobian(&self, q: &[T; 2]) -> na::Matrix2<T> {
+        let q01 = q[0].clone() + q[1].clone();
+        let (s0, c0) = q[0].clone().sin_cos();
+        let (s01, c01) = q01.sin_cos();
+        let (l1, l2) = (self.l1.clone(), self.l2.clone());
+        na::Matrix2::new(
+            -l1.clone() * s0 - l2.clone() * s01.clone(),
+            -l2.clone() * s01,
+            l1 * c0 + l2.clone() * c01.clone(),
+            l2 * c01,
+        )
+    }
+
+    pub fn is_reachable(&self, p: &na::Vector2<T>) -> bool {
+        self.check(p).is_ok()
+    }
+
+    fn check(&self, p: &na::Vector2<T>) -> Result<(), IkError> {
+        if self.l1 <= T::zero() || self.l2 <= T::zero() {
+            return Err(IkError::InvalidLength);
+        }
+        let r = p.norm();
+        let r_max = self.l1.clone() + self.l2.clone();
+        if r > r_max {
+            return Err(IkError::TooFar);
+        }
+        // the folded arm reaches the origin when l1 == l2, up to rounding
+        let r_min = (self.l1.clone() - self.l2.clone()).abs();
+        if r < r_min - T::default_epsilon().sqrt() * r_max {
+            return Err(IkError::TooNear);
+        }
+        Ok(())
+    }
+
+    /// Both elbow branches reaching `p`, with `q[1]` in [0, π] first and in [-π, 0] second.
+    pub fn solve(&self, p: &na::Vector2<T>) -> Result<[[T; 2]; 2], IkError> {
+        self.check(p)?;
+        let (l1, l2) = (self.l1.clone(), self.l2.clone());
+        let r_sq = p.norm_squared();
+        // 2 l1 l2 sin(q[1]) from the triangle area, clamped against rounding on the workspace boundary
+        let sin = (((l1.clone() + l2.clone()) * (l1.clone() + l2.clone()) - r_sq.clone())
+            * (r_sq.clone() - (l1.clone() - l2.clone()) * (l1.clone() - l2.clone())))
+        .max(T::zero())
+        .sqrt();
+        let cos = r_sq - l1.clone() * l1.clone() - l2.clone() * l2.clone();
+
+        let mut ans = [[T::zero(), T::zero()], [T::zero(), T::zero()]];
+        for (i, sign) in [T::one(), -T::one()].into_iter().enumerate() {
+            let q1 = (sign * sin.clone()).atan2(cos.clone());
+            let (s1, c1) = q1.clone().sin_cos();
+            let q0 = p.y.clone().atan2(p.x.clone())
+                - (l2.clone() * s1).atan2(l1.clone() + l2.clone() * c1);
+            ans[i] = [wrap_angle(q0), q1];
+        }
+        Ok(ans)
+    }
+}
+
+/// Planar chain of three revolute joints about +z, with the pose `(x, y, phi)`
+/// of the tip where `phi = q[0] + q[1] + q[2]`.
+pub struct Planar3R<T> {
+    pub l1: T,
+    pub l2: T,
+    pub l3: T,
+}
+
+impl<T: na::RealField> Default for Planar3R<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Planar3R<T> {
+    pub fn new() -> Self {
+        Self {
+            l1: T::zero(),
+            l2: T::zero(),
+            l3: T::zero(),
+        }
+    }
+
+    fn arm(&self) -> Planar2R<T> {
+        Planar2R {
+            l1: self.l1.clone(),
+            l2: self.l2.clone(),
+        }
+    }
+
+    fn wrist(&self, pose: &na::Vector3<T>) -> na::Vector2<T> {
+        let (s, c) = pose.z.clone().sin_cos();
+        na::Vector2::new(
+            pose.x.clone() - self.l3.clone() * c,
+            pose.y.clone() - self.l3.clone() * s,
+        )
+    }
+
+    pub fn forward(&self, q: &[T; 3]) -> na::Vector3<T> {
+        let phi = q[0].clone() + q[1].clone() + q[2].clone();
+        let p = self.arm().forward(&[q[0].clone(), q[1].clone()]);
+        let (s, c) = phi.clone().sin_cos();
+        na::Vector3::new(
+            p.x.clone() + self.l3.clone() * c,
+            p.y.clone() + self.l3.clone() * s,
+            phi,
+        )
+    }
+
+    /// Jacobian of `(x, y, phi)` with respect to `q`.
+    pub fn jacobian(&self, q: &[T; 3]) -> na::Matrix3<T> {
+        let arm = self.arm().jacobian(&[q[0].clone(), q[1].clone()]);
+        let phi = q[0].clone() + q[1].clone() + q[2].clone();
+        let (s, c) = phi.sin_cos();
+        let dx = -self.l3.clone() * s;
+        let dy = self.l3.clone() * c;
+        na::Matrix3::new(
+            arm[(0, 0)].clone() + dx.clone(),
+            arm[(0, 1)].clone() + dx.clone(),
+            dx,
+            arm[(1, 0)].clone() + dy.clone(),
+            arm[(1, 1)].clone() + dy.clone(),
+            dy,
+            T::one(),
+            T::one(),
+            T::one(),
+        )
+    }
+
+    pub fn is_reachable(&self, pose: &na::Vector3<T>) -> bool {
+        self.arm().is_reachable(&self.wrist(pose))
+    }
+
+    /// Both elbow branches reaching `pose`, ordered as in `Planar2R::solve`.
+    pub fn solve(&self, pose: &na::Vector3<T>) -> Result<[[T; 3]; 2], IkError> {
+        let arm = self.arm().solve(&self.wrist(pose))?;
+        let tip = |q: &[T; 2]| {
+            [
+                q[0].clone(),
+                q[1].clone(),
+                wrap_angle(pose.z.clone() - q[0].clone() - q[1].clone()),
+            ]
+        };
+        Ok([tip(&arm[0]), tip(&arm[1])])
+    }
+}
+
+#[cfg(test)]
+mod test_planar {
+    use crate::ik::{wrap_angle, IkError};
+    use crate::planar::{Planar2R, Planar3R};
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn planar_2r() {
+        let mut arm = Planar2R::<f32>::new();
+        arm.l1 = 2.0;
+        arm.l2 = 2.0;
+        let ans = arm.solve(&na::Vector2::new(2.0, 2.0)).unwrap();
+        assert_relative_eq!(ans[0][0], 0.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ans[0][1], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ans[1][0], core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ans[1][1], -core::f32::consts::PI / 2.0, epsilon = 1.0e-6);
+
+        arm.l2 = 1.5;
+        for q in [[0.2, 0.3], [-2.0, 1.0], [2.5, -2.0]] {
+            let p = arm.forward(&q);
+            let ans = arm.solve(&p).unwrap();
+            for sol in ans.iter() {
+                assert_relative_eq!(arm.forward(sol), p, epsilon = 1.0e-5);
+            }
+            let sol = if q[1] >= 0.0 { ans[0] } else { ans[1] };
+            assert_relative_eq!(wrap_angle(sol[0] - q[0]), 0.0, epsilon = 1.0e-5);
+            assert_relative_eq!(sol[1], q[1], epsilon = 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn planar_2r_reachability() {
+        let mut arm = Planar2R::<f32>::new();
+        assert_eq!(
+            arm.solve(&na::Vector2::new(1.0, 0.0)),
+            Err(IkError::InvalidLength)
+        );
+        arm.l1 = 2.0;
+        arm.l2 = 1.0;
+        assert!(arm.is_reachable(&na::Vector2::new(0.0, 3.0)));
+        assert_eq!(arm.solve(&na::Vector2::new(3.1, 0.0)), Err(IkError::TooFar));
+        assert_eq!(
+            arm.solve(&na::Vector2::new(0.0, 0.9)),
+            Err(IkError::TooNear)
+        );
+
+        // fully stretched, both branches coincide
+        let ans = arm.solve(&na::Vector2::new(0.0, 3.0)).unwrap();
+        assert_relative_eq!(ans[0][1], 0.0, epsilon = 1.0e-6);
+        assert_relative_eq!(ans[1][1], 0.0, epsilon = 1.0e-6);
+
+        // equal links fold onto themselves to reach the origin
+        arm.l2 = 2.0;
+        for sol in arm.solve(&na::Vector2::zeros()).unwrap() {
+            assert_relative_eq!(sol[1].abs(), core::f32::consts::PI, epsilon = 1.0e-6);
+            assert_relative_eq!(arm.forward(&sol), na::Vector2::zeros(), epsilon = 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn planar_3r() {
+        let mut arm = Planar3R::<f32>::new();
+        arm.l1 = 2.0;
+        arm.l2 = 1.5;
+        arm.l3 = 0.5;
+        for q in [[0.2, 0.3, -0.4], [-2.0, 1.0, 2.5], [2.5, -2.0, 0.1]] {
+            let pose = arm.forward(&q);
+            for sol in arm.solve(&pose).unwrap().iter() {
+                let p = arm.forward(sol);
+                assert_relative_eq!(p.xy(), pose.xy(), epsilon = 1.0e-5);
+                assert_relative_eq!(wrap_angle(p.z - pose.z), 0.0, epsilon = 1.0e-5);
+            }
+        }
+        assert!(!arm.is_reachable(&na::Vector3::new(4.5, 0.0, 0.0)));
+        assert!(arm.is_reachable(&na::Vector3::new(4.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn jacobian() {
+        let mut arm = Planar3R::<f64>::new();
+        arm.l1 = 2.0;
+        arm.l2 = 1.5;
+        arm.l3 = 0.5;
+        let q = [0.3, -0.7, 1.1];
+        let jac = arm.jacobian(&q);
+        let h = 1.0e-6;
+        for k in 0..3 {
+            let mut plus = q;
+            let mut minus = q;
+            plus[k] += h;
+            minus[k] -= h;
+            let diff = (arm.forward(&plus) - arm.forward(&minus)) / (2.0 * h);
+            assert_relative_eq!(jac.column(k).into_owned(), diff, epsilon = 1.0e-6);
+        }
+
+        let arm = Planar2R { l1: 2.0, l2: 1.5 };
+        let q = [0.3, -0.7];
+        let jac = arm.jacobian(&q);
+        for k in 0..2 {
+            let mut plus = q;
+            let mut minus = q;
+            plus[k] += h;
+            minus[k] -= h;
+            let diff = (arm.forward(&plus) - arm.forward(&minus)) / (2.0 * h);
+            assert_relative_eq!(jac.column(k).into_owned(), diff, epsilon = 1.0e-6);
+        }
+    }
+}
@@ -7,21 +7,37 @@ use crate::link::Link;
 /// Robot Kinematics and Dynamics
 pub struct Rkd<T> {
     pub links: Vec<Link<T>, 256>,
+    pub gravity: na::Vector3<T>, // gravitational acceleration in world coordinate
+    pub base: na::Isometry3<T>,  // pose of the root link in world coordinate
+}
+
+impl<T: na::RealField> Default for Rkd<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T: na::RealField> Rkd<T> {
     pub fn new() -> Self {
-        Self { links: Vec::new() }
+        Self {
+            links: Vec::new(),
+            gravity: na::Vector3::zeros(),
+            base: na::Isometry3::identity(),
+        }
     }
 
     pub fn update_kinematic_relationship(&mut self) {
         // Currently id 0 must be root link.
-        self.links[0].r_quat = na::UnitQuaternion::identity();
-        self.links[0].p = na::Vector3::<T>::zeros();
+        self.links[0].r_quat = self.base.rotation.clone();
+        self.links[0].p = self.base.translation.vector.clone();
         self.links[0].w_vec = na::Vector3::zeros();
         self.links[0].dwdt_vec = na::Vector3::zeros();
-        // self.links[0].ddpddt = na::Vector3::zeros();
-        self.links[0].ddsddt = na::Vector3::zeros();
+        // gravity enters as an upward acceleration of the fixed root
+        self.links[0].ddpddt = self
+            .base
+            .rotation
+            .inverse_transform_vector(&-self.gravity.clone());
+        self.links[0].ddsddt = self.links[0].ddpddt.clone();
 
         let mut ids = Deque::<u8, 256>::new();
         for id in self.links[0].children.clone() {
@@ -29,7 +45,7 @@ impl<T: na::RealField> Rkd<T> {
         }
 
         // DFS
-        while ids.len() > 0 {
+        while !ids.is_empty() {
             let i = ids.pop_back().unwrap() as usize;
             for id in self.links[i].children.clone() {
                 ids.push_back(id).unwrap();
@@ -46,7 +62,8 @@ impl<T: na::RealField> Rkd<T> {
                 + self.links[i].a.clone().into_inner() * self.links[i].dqdt.clone();
             self.links[i].dwdt_vec = pr.inverse().transform_vector(&self.links[pi].dwdt_vec)
                 + self.links[i].a.clone().into_inner() * self.links[i].ddqddt.clone()
-                + pr.transform_vector(&self.links[pi].w_vec)
+                + pr.inverse()
+                    .transform_vector(&self.links[pi].w_vec)
                     .cross(&(self.links[i].a.clone().into_inner() * self.links[i].dqdt.clone()));
 
             self.links[i].ddpddt = pr.inverse().transform_vector(
@@ -64,7 +81,444 @@ impl<T: na::RealField> Rkd<T> {
         }
     }
 
+    /// Link ids in depth-first order from the root, every parent before its children.
+    fn preorder(&self) -> Vec<usize, 256> {
+        let mut order = Vec::<usize, 256>::new();
+        let mut ids = Deque::<u8, 256>::new();
+        ids.push_back(0).unwrap();
+        while !ids.is_empty() {
+            let i = ids.pop_back().unwrap() as usize;
+            order.push(i).unwrap();
+            for id in self.links[i].children.clone() {
+                ids.push_back(id).unwrap();
+            }
+        }
+        order
+    }
+
+    /// Joint-space inertia matrix by the composite rigid body algorithm.
+    ///
+    /// `N` must be the number of links. Row and column `i` belong to the
+    /// joint of link `i`, so those of the root link 0 stay zero. The
+    /// armatures of the actuators are on the diagonal. Uses the poses of the
+    /// last `update_kinematic_relationship`.
+    ///
+    /// The zero row and column leave the whole matrix singular. Solve with
+    /// its joint block, `m.fixed_slice::<J, J>(1, 1)` for `J = N - 1` joints.
+    ///
+    /// # Panics
+    ///
+    /// If `N` differs from `links.len()`.
+    pub fn mass_matrix<const N: usize>(&self) -> na::SMatrix<T, N, N> {
+        assert_eq!(N, self.links.len());
+        let order = self.preorder();
+        let (mass, h, inertia) = self.composites(&order);
+
+        let mut mat = na::SMatrix::<T, N, N>::zeros();
+        for &i in order.iter().skip(1) {
+            // wrench about the world origin moving subtree i at unit joint acceleration
+            let (force, moment) = self.unit_momentum(i, &mass[i], &h[i], &inertia[i]);
+
+            let mut j = i;
+            loop {
+                let sj = self.links[j].r_quat.transform_vector(&self.links[j].a);
+                let value = sj.dot(&(moment.clone() - self.links[j].p.cross(&force)));
+                mat[(j, i)] = value.clone();
+                mat[(i, j)] = value;
+                j = self.links[j].parent as usize;
+                if j == 0 {
+                    break;
+                }
+            }
+            mat[(i, i)] += self.links[i].actuator.armature();
+        }
+        mat
+    }
+
+    /// Sum of the link masses.
+    pub fn total_mass(&self) -> T {
+        self.links.iter().fold(T::zero(), |m, l| m + l.mass.clone())
+    }
+
+    /// Center of mass of the whole robot in world coordinate.
+    pub fn com(&self) -> na::Vector3<T> {
+        let mut h = na::Vector3::zeros();
+        for l in self.links.iter() {
+            h += (l.p.clone() + l.r_quat.transform_vector(&l.com)) * l.mass.clone();
+        }
+        h / self.total_mass()
+    }
+
+    /// Jacobian of `com` with respect to the joint speeds, laid out as the
+    /// columns of `mass_matrix::<N>`. Column 0 of the root link stays zero, so
+    /// take `fixed_columns::<J>(1)` for the `J = N - 1` joints.
+    ///
+    /// # Panics
+    ///
+    /// If `N` differs from `links.len()`.
+    pub fn com_jacobian<const N: usize>(&self) -> na::SMatrix<T, 3, N> {
+        let cmm = self.centroidal_momentum_matrix::<N>();
+        cmm.fixed_rows::<3>(0).into_owned() / self.total_mass()
+    }
+
+    /// Linear momentum of the whole robot in world coordinate.
+    pub fn linear_momentum(&self) -> na::Vector3<T> {
+        let (w, v) = self.velocities();
+        let mut momentum = na::Vector3::zeros();
+        for (i, l) in self.links.iter().enumerate() {
+            let r = l.r_quat.transform_vector(&l.com);
+            momentum += (v[i].clone() + w[i].cross(&r)) * l.mass.clone();
+        }
+        momentum
+    }
+
+    /// Angular momentum of the whole robot about its center of mass, in
+    /// world coordinate.
+    pub fn angular_momentum(&self) -> na::Vector3<T> {
+        let (w, v) = self.velocities();
+        let com = self.com();
+        let mut momentum = na::Vector3::zeros();
+        for (i, l) in self.links.iter().enumerate() {
+            let r = l.r_quat.transform_vector(&l.com);
+            let c = l.p.clone() + r.clone() - com.clone();
+            let vc = v[i].clone() + w[i].cross(&r);
+            let rot = l.r_quat.clone().to_rotation_matrix().into_inner();
+            momentum += c.cross(&vc) * l.mass.clone()
+                + rot.clone() * l.inertia_mat.clone() * rot.transpose() * w[i].clone();
+        }
+        momentum
+    }
+
+    /// Centroidal momentum matrix `A`, so that `A dq` is the linear momentum
+    /// over the angular momentum about the center of mass.
+    ///
+    /// Columns are laid out as in `mass_matrix::<N>`, and column 0 of the
+    /// root link stays zero. Take `fixed_columns::<J>(1)` for the `J = N - 1`
+    /// joints.
+    ///
+    /// # Panics
+    ///
+    /// If `N` differs from `links.len()`.
+    pub fn centroidal_momentum_matrix<const N: usize>(&self) -> na::SMatrix<T, 6, N> {
+        assert_eq!(N, self.links.len());
+        let order = self.preorder();
+        let (mass, h, inertia) = self.composites(&order);
+        let com = h[0].clone() / mass[0].clone();
+        let mut mat = na::SMatrix::<T, 6, N>::zeros();
+        for &i in order.iter().skip(1) {
+            let (linear, angular) = self.unit_momentum(i, &mass[i], &h[i], &inertia[i]);
+            let angular = angular - com.cross(&linear);
+            mat.fixed_slice_mut::<3, 1>(0, i).copy_from(&linear);
+            mat.fixed_slice_mut::<3, 1>(3, i).copy_from(&angular);
+        }
+        mat
+    }
+
+    /// Angular velocities of the links and velocities of their origins, in
+    /// world coordinate.
+    fn velocities(&self) -> (Vec<na::Vector3<T>, 256>, Vec<na::Vector3<T>, 256>) {
+        let mut w = Vec::<na::Vector3<T>, 256>::new();
+        w.resize(self.links.len(), na::Vector3::zeros()).unwrap();
+        let mut v = w.clone();
+        for &i in self.preorder().iter().skip(1) {
+            let link = &self.links[i];
+            let pi = link.parent as usize;
+            v[i] = v[pi].clone() + w[pi].cross(&(link.p.clone() - self.links[pi].p.clone()));
+            w[i] = link.r_quat.transform_vector(&link.w_vec);
+        }
+        (w, v)
+    }
+
+    /// Mass, first moment and inertia about the world origin of the subtree
+    /// of each link, from the poses of the last `update_kinematic_relationship`.
+    fn composites(
+        &self,
+        order: &[usize],
+    ) -> (
+        Vec<T, 256>,
+        Vec<na::Vector3<T>, 256>,
+        Vec<na::Matrix3<T>, 256>,
+    ) {
+        let n = self.links.len();
+        let mut mass = Vec::<T, 256>::new();
+        mass.resize(n, T::zero()).unwrap();
+        let mut h = Vec::<na::Vector3<T>, 256>::new();
+        h.resize(n, na::Vector3::zeros()).unwrap();
+        let mut inertia = Vec::<na::Matrix3<T>, 256>::new();
+        inertia.resize(n, na::Matrix3::zeros()).unwrap();
+
+        for &i in order.iter().rev() {
+            let link = &self.links[i];
+            let rot = link.r_quat.clone().to_rotation_matrix().into_inner();
+            let c = link.p.clone() + rot.clone() * link.com.clone();
+            let m = link.mass.clone();
+            mass[i] += m.clone();
+            h[i] += c.clone() * m.clone();
+            inertia[i] += rot.clone() * link.inertia_mat.clone() * rot.transpose()
+                + (na::Matrix3::identity() * c.norm_squared() - c.clone() * c.transpose()) * m;
+            if i != 0 {
+                let pi = link.parent as usize;
+                let (m, hi, ii) = (mass[i].clone(), h[i].clone(), inertia[i].clone());
+                mass[pi] += m;
+                h[pi] += hi;
+                inertia[pi] += ii;
+            }
+        }
+        (mass, h, inertia)
+    }
+
+    /// Momentum `(linear, angular about the world origin)` of the subtree of
+    /// link `i` moving at unit speed of its joint.
+    fn unit_momentum(
+        &self,
+        i: usize,
+        mass: &T,
+        h: &na::Vector3<T>,
+        inertia: &na::Matrix3<T>,
+    ) -> (na::Vector3<T>, na::Vector3<T>) {
+        let s = self.links[i].r_quat.transform_vector(&self.links[i].a);
+        let p = self.links[i].p.clone();
+        let linear = s.cross(&(h.clone() - p.clone() * mass.clone()));
+        let angular = inertia.clone() * s.clone() - h.cross(&s.cross(&p));
+        (linear, angular)
+    }
+
+    /// Run `f` with the joints set to `q`, `dq` and zero acceleration, and
+    /// gravity on or off, then put the previous state back.
+    fn with_state<R>(
+        &mut self,
+        q: &[T],
+        dq: &[T],
+        gravity: bool,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let mut saved = Vec::<(T, T, T), 256>::new();
+        for (i, link) in self.links.iter_mut().enumerate() {
+            saved
+                .push((link.q.clone(), link.dqdt.clone(), link.ddqddt.clone()))
+                .unwrap();
+            link.q = q[i].clone();
+            link.dqdt = dq[i].clone();
+            link.ddqddt = T::zero();
+        }
+        let g = self.gravity.clone();
+        if !gravity {
+            self.gravity = na::Vector3::zeros();
+        }
+        self.update_kinematic_relationship();
+
+        let ans = f(self);
+
+        for (link, (q, dq, ddq)) in self.links.iter_mut().zip(saved) {
+            link.q = q;
+            link.dqdt = dq;
+            link.ddqddt = ddq;
+        }
+        self.gravity = g;
+        self.update_kinematic_relationship();
+        ans
+    }
+
+    /// Joint torques holding the robot still at `q` against `gravity`.
+    /// `q` is indexed by link id, as the torques are.
+    /// External wrenches and actuators are left out.
+    pub fn gravity_torques(&mut self, q: &[T]) -> Vec<T, 256> {
+        let mut dq = Vec::<T, 256>::new();
+        dq.resize(self.links.len(), T::zero()).unwrap();
+        self.with_state(q, &dq, true, |rkd| rkd.newton_euler(false))
+    }
+
+    /// Coriolis and centrifugal joint torques `C(q, dq) dq`, without gravity,
+    /// external wrenches and actuators.
+    pub fn coriolis_torques(&mut self, q: &[T], dq: &[T]) -> Vec<T, 256> {
+        self.with_state(q, dq, false, |rkd| rkd.newton_euler(false))
+    }
+
+    /// Coriolis matrix `C(q, dq)`, laid out as `mass_matrix::<N>`.
+    ///
+    /// `C` is built from the link Jacobians and their derivatives, so that
+    /// `Ṁ - 2C` is skew-symmetric. Only the joint block
+    /// `fixed_slice::<J, J>(1, 1)` is meaningful, as in `mass_matrix`.
+    ///
+    /// # Panics
+    ///
+    /// If `N` differs from `links.len()`.
+    pub fn coriolis_matrix<const N: usize>(&mut self, q: &[T], dq: &[T]) -> na::SMatrix<T, N, N> {
+        assert_eq!(N, self.links.len());
+        self.with_state(q, dq, false, |rkd| {
+            let order = rkd.preorder();
+            // world joint axes, angular velocities, and velocities of the joint points
+            let mut s = Vec::<na::Vector3<T>, 256>::new();
+            s.resize(N, na::Vector3::zeros()).unwrap();
+            let mut w = s.clone();
+            let mut v = s.clone();
+            for &i in order.iter().skip(1) {
+                let link = &rkd.links[i];
+                let pi = link.parent as usize;
+                s[i] = link.r_quat.transform_vector(&link.a);
+                v[i] = v[pi].clone() + w[pi].cross(&(link.p.clone() - rkd.links[pi].p.clone()));
+                w[i] = w[pi].clone() + s[i].clone() * link.dqdt.clone();
+            }
+
+            let mut mat = na::SMatrix::<T, N, N>::zeros();
+            for &k in order.iter().skip(1) {
+                let link = &rkd.links[k];
+                let rot = link.r_quat.clone().to_rotation_matrix().into_inner();
+                let inertia = rot.clone() * link.inertia_mat.clone() * rot.transpose();
+                let c = link.p.clone() + link.r_quat.transform_vector(&link.com);
+                let vc = v[k].clone() + w[k].cross(&(c.clone() - link.p.clone()));
+
+                // columns of the COM Jacobians of link k and their derivatives
+                let mut jv = na::SMatrix::<T, 3, N>::zeros();
+                let mut jw = na::SMatrix::<T, 3, N>::zeros();
+                let mut djv = na::SMatrix::<T, 3, N>::zeros();
+                let mut djw = na::SMatrix::<T, 3, N>::zeros();
+                let mut j = k;
+                while j != 0 {
+                    let r = c.clone() - rkd.links[j].p.clone();
+                    let ds = w[j].cross(&s[j]);
+                    jv.set_column(j, &s[j].cross(&r));
+                    jw.set_column(j, &s[j]);
+                    djv.set_column(
+                        j,
+                        &(ds.cross(&r) + s[j].cross(&(vc.clone() - v[j].clone()))),
+                    );
+                    djw.set_column(j, &ds);
+                    j = rkd.links[j].parent as usize;
+                }
+                let wx = w[k].cross_matrix();
+
+                mat += jv.transpose() * djv * link.mass.clone()
+                    + jw.transpose() * (inertia.clone() * djw + wx * inertia * jw.clone());
+            }
+            mat
+        })
+    }
+
+    /// Joint accelerations for the joint torques `tau` at the current `q`
+    /// and `dq`, by the articulated body algorithm. They are written to
+    /// `ddqddt` of every link, and the kinematics are updated with them.
+    ///
+    /// `tau` is indexed by link id, as the torques of
+    /// `update_equation_of_motion`. The `wrenches` of the links are applied,
+    /// as well as `f_ext`, also indexed by link id, holding further external
+    /// wrenches `(force, moment)` in the world frame, the moment taken about
+    /// the link origin `p`.
+    pub fn forward_dynamics(&mut self, tau: &[T], f_ext: Option<&[na::Vector6<T>]>) {
+        self.update_kinematic_relationship();
+        let n = self.links.len();
+        let order = self.preorder();
+
+        // spatial quantities in the world frame, at the world origin
+        let mut s = Vec::<na::Vector6<T>, 256>::new();
+        s.resize(n, na::Vector6::zeros()).unwrap();
+        let mut v = s.clone();
+        let mut c = s.clone();
+        let mut pa = s.clone();
+        let mut ia = Vec::<na::Matrix6<T>, 256>::new();
+        ia.resize(n, na::Matrix6::zeros()).unwrap();
+
+        for &i in order.iter() {
+            let link = &self.links[i];
+            if i != 0 {
+                let axis = link.r_quat.transform_vector(&link.a);
+                s[i] = motion(&axis, &link.p.cross(&axis));
+                v[i] = v[link.parent as usize].clone() + s[i].clone() * link.dqdt.clone();
+                c[i] = motion_cross(&v[i]) * s[i].clone() * link.dqdt.clone();
+            }
+            ia[i] = spatial_inertia(link);
+            pa[i] = -motion_cross(&v[i]).transpose() * (ia[i].clone() * v[i].clone());
+            let (force, moment) = link.external_wrench();
+            let force = link.r_quat.transform_vector(&force);
+            let moment = link.r_quat.transform_vector(&moment);
+            pa[i] -= force_vector(&(moment + link.p.cross(&force)), &force);
+            if let Some(f_ext) = f_ext {
+                let force = f_ext[i].fixed_rows::<3>(0).into_owned();
+                let moment = f_ext[i].fixed_rows::<3>(3).into_owned();
+                pa[i] -= force_vector(&(moment + link.p.cross(&force)), &force);
+            }
+        }
+
+        let mut u = Vec::<na::Vector6<T>, 256>::new();
+        u.resize(n, na::Vector6::zeros()).unwrap();
+        let mut d = Vec::<T, 256>::new();
+        d.resize(n, T::zero()).unwrap();
+        let mut bias = d.clone();
+        for &i in order.iter().rev().filter(|&&i| i != 0) {
+            u[i] = ia[i].clone() * s[i].clone();
+            let actuator = &self.links[i].actuator;
+            d[i] = s[i].dot(&u[i]) + actuator.armature();
+            bias[i] =
+                tau[i].clone() - actuator.friction(self.links[i].dqdt.clone()) - s[i].dot(&pa[i]);
+            let ia_i = ia[i].clone() - u[i].clone() * u[i].transpose() / d[i].clone();
+            let pa_i = pa[i].clone()
+                + ia_i.clone() * c[i].clone()
+                + u[i].clone() * (bias[i].clone() / d[i].clone());
+            let pi = self.links[i].parent as usize;
+            ia[pi] += ia_i;
+            pa[pi] += pa_i;
+        }
+
+        let mut a = Vec::<na::Vector6<T>, 256>::new();
+        a.resize(n, na::Vector6::zeros()).unwrap();
+        a[0] = motion(&na::Vector3::zeros(), &-self.gravity.clone());
+        for &i in order.iter().skip(1) {
+            let ap = a[self.links[i].parent as usize].clone() + c[i].clone();
+            let ddq = (bias[i].clone() - u[i].dot(&ap)) / d[i].clone();
+            a[i] = ap + s[i].clone() * ddq.clone();
+            self.links[i].ddqddt = ddq;
+        }
+        self.update_kinematic_relationship();
+    }
+
+    /// Motor torques giving the joint torques `tau` of
+    /// `update_equation_of_motion` at the current `dq`, through the gear
+    /// ratio and efficiency of each actuator. Indexed by link id, with the
+    /// root link 0 at zero.
+    pub fn motor_torques(&self, tau: &[T]) -> Vec<T, 256> {
+        let mut t = Vec::<T, 256>::new();
+        t.push(T::zero()).unwrap();
+        for (l, tau) in self.links.iter().zip(tau.iter()).skip(1) {
+            t.push(l.actuator.motor_torque(tau.clone(), l.dqdt.clone()))
+                .unwrap();
+        }
+        t
+    }
+
+    /// Joint torques for `forward_dynamics` of the motor torques `tau_motor`
+    /// at the current `dq`, the inverse of `motor_torques`.
+    pub fn joint_torques(&self, tau_motor: &[T]) -> Vec<T, 256> {
+        let mut t = Vec::<T, 256>::new();
+        t.push(T::zero()).unwrap();
+        for (l, tau) in self.links.iter().zip(tau_motor.iter()).skip(1) {
+            t.push(l.actuator.joint_torque(tau.clone(), l.dqdt.clone()))
+                .unwrap();
+        }
+        t
+    }
+
+    /// Pose of link `i` in the world frame, from the last `update_kinematic_relationship`.
+    pub fn link_pose(&self, i: usize) -> na::Isometry3<T> {
+        na::Isometry3::from_parts(
+            na::Translation3::from(self.links[i].p.clone()),
+            self.links[i].r_quat.clone(),
+        )
+    }
+
+    /// Joint torques by the Newton–Euler backward pass, with the external
+    /// `wrenches` of the links and the `actuator` of each joint taken into account.
+    ///
+    /// The full wrench the parent exerts on each link through its joint is
+    /// left in `f_vec` and `n_vec`, the moment taken about the link origin.
+    /// Those of link 0 are the reaction of the base. The torque `n_vec·a`
+    /// is before the friction and armature of the actuator.
     pub fn update_equation_of_motion(&mut self) -> Vec<T, 256> {
+        self.newton_euler(true)
+    }
+
+    /// Rigid body joint torques, plus the external wrenches and the
+    /// actuators when `full` is set, which also keeps the joint reactions.
+    fn newton_euler(&mut self, full: bool) -> Vec<T, 256> {
         let mut f_hat = Vec::<na::Vector3<T>, 256>::new();
         f_hat
             .resize(self.links.len(), na::Vector3::zeros())
@@ -97,21 +551,26 @@ impl<T: na::RealField> Rkd<T> {
         n.resize(self.links.len(), na::Vector3::zeros()).unwrap();
 
         // DFS return order
-        while ids.len() > 0 {
+        while !ids.is_empty() {
             let i = *ids.back().unwrap() as usize;
             let mut flag = true;
             for c in self.links[i].children.clone() {
-                if seen[c as usize] == false {
+                if !seen[c as usize] {
                     ids.push_back(c).unwrap();
                     flag = false;
                 }
             }
             seen[i] = true;
-            if flag == false {
+            if !flag {
                 continue;
             }
             f[i] = f_hat[i].clone();
             n[i] = n_hat[i].clone() + self.links[i].com.cross(&f_hat[i]);
+            if full {
+                let (f_ext, n_ext) = self.links[i].external_wrench();
+                f[i] -= f_ext;
+                n[i] -= n_ext;
+            }
             for c in self.links[i].children.clone() {
                 let r = na::UnitQuaternion::from_axis_angle(
                     &self.links[c as usize].a,
@@ -127,18 +586,75 @@ impl<T: na::RealField> Rkd<T> {
             }
             ids.pop_back().unwrap();
         }
+        if full {
+            for (link, (f, n)) in self.links.iter_mut().zip(f.iter().zip(n.iter())) {
+                link.f_vec = f.clone();
+                link.n_vec = n.clone();
+            }
+        }
         let mut t = Vec::<T, 256>::new();
         t.resize(self.links.len(), T::zero()).unwrap();
         for i in 0..self.links.len() {
             t[i] = n[i].clone().dot(&self.links[i].a);
+            if full && i != 0 {
+                let actuator = &self.links[i].actuator;
+                t[i] += actuator.armature() * self.links[i].ddqddt.clone()
+                    + actuator.friction(self.links[i].dqdt.clone());
+            }
         }
         t
     }
 }
 
+/// Spatial motion vector of the angular part `w` and linear part `v`.
+fn motion<T: na::RealField>(w: &na::Vector3<T>, v: &na::Vector3<T>) -> na::Vector6<T> {
+    na::Vector6::new(
+        w.x.clone(),
+        w.y.clone(),
+        w.z.clone(),
+        v.x.clone(),
+        v.y.clone(),
+        v.z.clone(),
+    )
+}
+
+/// Spatial force vector of the moment `n` and force `f`.
+fn force_vector<T: na::RealField>(n: &na::Vector3<T>, f: &na::Vector3<T>) -> na::Vector6<T> {
+    motion(n, f)
+}
+
+/// Matrix of `v ×` acting on spatial motion vectors. Its negative
+/// transpose acts on spatial force vectors.
+fn motion_cross<T: na::RealField>(v: &na::Vector6<T>) -> na::Matrix6<T> {
+    let w = v.fixed_rows::<3>(0).into_owned().cross_matrix();
+    let mut m = na::Matrix6::zeros();
+    m.fixed_slice_mut::<3, 3>(0, 0).copy_from(&w);
+    m.fixed_slice_mut::<3, 3>(3, 3).copy_from(&w);
+    m.fixed_slice_mut::<3, 3>(3, 0)
+        .copy_from(&v.fixed_rows::<3>(3).into_owned().cross_matrix());
+    m
+}
+
+/// Spatial inertia of `link` in the world frame, at the world origin.
+fn spatial_inertia<T: na::RealField>(link: &Link<T>) -> na::Matrix6<T> {
+    let rot = link.r_quat.clone().to_rotation_matrix().into_inner();
+    let c = link.p.clone() + rot.clone() * link.com.clone();
+    let cx = c.cross_matrix() * link.mass.clone();
+    let mut m = na::Matrix6::zeros();
+    m.fixed_slice_mut::<3, 3>(0, 0).copy_from(
+        &(rot.clone() * link.inertia_mat.clone() * rot.transpose()
+            + c.cross_matrix() * c.cross_matrix().transpose() * link.mass.clone()),
+    );
+    m.fixed_slice_mut::<3, 3>(0, 3).copy_from(&cx);
+    m.fixed_slice_mut::<3, 3>(3, 0).copy_from(&cx.transpose());
+    m.fixed_slice_mut::<3, 3>(3, 3)
+        .copy_from(&(na::Matrix3::identity() * link.mass.clone()));
+    m
+}
+
 #[cfg(test)]
 mod test_rkd {
-    use crate::link::Link;
+    use crate::link::{Frame, Link, Wrench};
     use crate::rkd::Rkd;
     use approx::assert_relative_eq;
 
@@ -190,6 +706,533 @@ mod test_rkd {
         rkd.update_equation_of_motion();
     }
 
+    /// Branching 3D tree with joint axes along x, y, z and a slanted one.
+    fn tree() -> Rkd<f64> {
+        let mut rkd = Rkd::<f64>::new();
+        let axes = [
+            na::Vector3::x_axis(),
+            na::Vector3::z_axis(),
+            na::Vector3::y_axis(),
+            na::Vector3::x_axis(),
+            na::Unit::new_normalize(na::Vector3::new(1.0, 1.0, 0.0)),
+            na::Vector3::y_axis(),
+        ];
+        let parents = [0, 0, 1, 2, 1, 4];
+        let bs = [
+            na::Vector3::zeros(),
+            na::Vector3::new(0.0, 0.0, 0.1),
+            na::Vector3::new(0.0, 0.0, 0.3),
+            na::Vector3::new(0.4, 0.0, 0.0),
+            na::Vector3::new(0.0, 0.2, 0.1),
+            na::Vector3::new(0.3, 0.0, 0.0),
+        ];
+        let qs = [0.0, 0.3, -0.5, 0.8, 0.4, -1.1];
+        let dqs = [0.0, 0.7, -0.4, 1.2, -0.9, 0.5];
+        for i in 0..6 {
+            let mut l = Link::new();
+            l.id = i as u8;
+            l.parent = parents[i];
+            l.a = axes[i];
+            l.b = bs[i];
+            l.q = qs[i];
+            l.dqdt = dqs[i];
+            l.mass = 1.0 + 0.5 * i as f64;
+            l.com = na::Vector3::new(0.1, 0.02 * i as f64, -0.05);
+            l.inertia_mat =
+                na::Matrix3::new(0.02, 0.001, 0.0, 0.001, 0.03, 0.002, 0.0, 0.002, 0.025)
+                    * (1.0 + 0.1 * i as f64);
+            rkd.links.push(l).unwrap();
+            if i != 0 {
+                rkd.links[parents[i] as usize]
+                    .children
+                    .push(i as u8)
+                    .unwrap();
+            }
+        }
+        rkd.update_kinematic_relationship();
+        rkd
+    }
+
+    #[test]
+    fn mass_matrix() {
+        let mut rkd = tree();
+        for l in rkd.links.iter_mut() {
+            l.dqdt = 0.0;
+        }
+        rkd.update_kinematic_relationship();
+        let m = rkd.mass_matrix::<6>();
+        assert_relative_eq!(m, m.transpose(), epsilon = 1.0e-12);
+        assert!(m
+            .fixed_slice::<5, 5>(1, 1)
+            .into_owned()
+            .cholesky()
+            .is_some());
+        for i in 0..6 {
+            assert_eq!(m[(0, i)], 0.0);
+        }
+
+        // column k is the torque of a unit acceleration of joint k
+        for k in 1..6 {
+            for (i, l) in rkd.links.iter_mut().enumerate() {
+                l.ddqddt = if i == k { 1.0 } else { 0.0 };
+            }
+            rkd.update_kinematic_relationship();
+            let t = rkd.update_equation_of_motion();
+            for i in 1..6 {
+                assert_relative_eq!(m[(i, k)], t[i], epsilon = 1.0e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn angular_acceleration() {
+        // dwdt_vec is the derivative of w_vec along the motion, also across
+        // non-parallel axes with nonzero joint speeds
+        let mut rkd = tree();
+        for (i, l) in rkd.links.iter_mut().enumerate() {
+            l.ddqddt = 0.4 - 0.15 * i as f64;
+        }
+        rkd.update_kinematic_relationship();
+        let dwdt: heapless::Vec<na::Vector3<f64>, 256> = rkd
+            .links
+            .iter()
+            .map(|l| l.r_quat.transform_vector(&l.dwdt_vec))
+            .collect();
+        let state: heapless::Vec<(f64, f64, f64), 256> =
+            rkd.links.iter().map(|l| (l.q, l.dqdt, l.ddqddt)).collect();
+
+        let h = 1.0e-6;
+        let mut fd = heapless::Vec::<na::Vector3<f64>, 256>::new();
+        fd.resize(6, na::Vector3::zeros()).unwrap();
+        for sign in [1.0, -1.0] {
+            let t = sign * h;
+            for (l, &(q, dq, ddq)) in rkd.links.iter_mut().zip(state.iter()) {
+                l.q = q + dq * t + 0.5 * ddq * t * t;
+                l.dqdt = dq + ddq * t;
+            }
+            rkd.update_kinematic_relationship();
+            for (d, l) in fd.iter_mut().zip(rkd.links.iter()) {
+                *d += l.r_quat.transform_vector(&l.w_vec) * (sign / (2.0 * h));
+            }
+        }
+        for i in 1..6 {
+            assert_relative_eq!(dwdt[i], fd[i], epsilon = 1.0e-6);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn mass_matrix_size() {
+        tree().mass_matrix::<5>();
+    }
+
+    #[test]
+    #[should_panic]
+    fn centroidal_momentum_matrix_size() {
+        tree().centroidal_momentum_matrix::<7>();
+    }
+
+    #[test]
+    fn gravity_and_coriolis() {
+        let mut rkd = tree();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.3;
+        }
+        let q: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.q).collect();
+        let dq: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.dqdt).collect();
+        let ddq: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.ddqddt).collect();
+
+        // M ddq + C dq + g is the full inverse dynamics
+        rkd.update_kinematic_relationship();
+        let t = rkd.update_equation_of_motion();
+        let m = rkd.mass_matrix::<6>();
+        let g = rkd.gravity_torques(&q);
+        let cdq = rkd.coriolis_torques(&q, &dq);
+        let c = rkd.coriolis_matrix::<6>(&q, &dq);
+        let ddq = na::SVector::<f64, 6>::from_column_slice(&ddq);
+        let dqv = na::SVector::<f64, 6>::from_column_slice(&dq);
+        let mddq = m * ddq;
+        let cdqv = c * dqv;
+        for i in 1..6 {
+            assert_relative_eq!(t[i], mddq[i] + cdq[i] + g[i], epsilon = 1.0e-9);
+            assert_relative_eq!(cdqv[i], cdq[i], epsilon = 1.0e-9);
+        }
+        // the state is put back
+        assert_eq!(rkd.links[4].ddqddt, 0.3);
+        assert_eq!(rkd.gravity, na::Vector3::new(0.0, 0.0, -9.8));
+
+        // Ṁ - 2C is skew-symmetric
+        let h = 1.0e-6;
+        let mut dm = na::SMatrix::<f64, 6, 6>::zeros();
+        for sign in [1.0, -1.0] {
+            for (l, (q, dq)) in rkd.links.iter_mut().zip(q.iter().zip(dq.iter())) {
+                l.q = q + sign * h * dq;
+            }
+            rkd.update_kinematic_relationship();
+            dm += rkd.mass_matrix::<6>() * (sign / (2.0 * h));
+        }
+        let n = dm - c * 2.0;
+        assert_relative_eq!(n, -n.transpose(), epsilon = 1.0e-6);
+    }
+
+    #[test]
+    fn forward_dynamics() {
+        let mut rkd = tree();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        let ddq = [0.0, 0.4, -1.3, 0.9, 2.1, -0.6];
+        for (l, ddq) in rkd.links.iter_mut().zip(ddq.iter()) {
+            l.ddqddt = *ddq;
+        }
+        rkd.update_kinematic_relationship();
+        let tau = rkd.update_equation_of_motion();
+
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.0;
+        }
+        rkd.forward_dynamics(&tau, None);
+        for (l, ddq) in rkd.links.iter().zip(ddq.iter()).skip(1) {
+            assert_relative_eq!(l.ddqddt, *ddq, epsilon = 1.0e-9);
+        }
+
+        // gravity as external forces at the centers of mass
+        let mut f_ext = [na::Vector6::zeros(); 6];
+        for (i, l) in rkd.links.iter().enumerate() {
+            let force = na::Vector3::new(0.0, 0.0, -9.8 * l.mass);
+            let arm = l.r_quat.transform_vector(&l.com);
+            f_ext[i].fixed_rows_mut::<3>(0).copy_from(&force);
+            f_ext[i]
+                .fixed_rows_mut::<3>(3)
+                .copy_from(&arm.cross(&force));
+        }
+        rkd.gravity = na::Vector3::zeros();
+        rkd.forward_dynamics(&tau, Some(&f_ext));
+        for (l, ddq) in rkd.links.iter().zip(ddq.iter()).skip(1) {
+            assert_relative_eq!(l.ddqddt, *ddq, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn external_wrenches() {
+        let mut rkd = tree();
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.5;
+        }
+        rkd.update_kinematic_relationship();
+        let free = rkd.update_equation_of_motion();
+
+        // gravity along -z as loads at the centers of mass, alternating frames
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        rkd.update_kinematic_relationship();
+        let expected = rkd.update_equation_of_motion();
+        rkd.gravity = na::Vector3::zeros();
+        rkd.update_kinematic_relationship();
+        for (i, l) in rkd.links.iter_mut().enumerate() {
+            let force = na::Vector3::new(0.0, 0.0, -9.8 * l.mass);
+            let mut w = Wrench::new();
+            w.point = l.com;
+            if i % 2 == 0 {
+                w.frame = Frame::World;
+                w.force = force;
+            } else {
+                w.force = l.r_quat.inverse_transform_vector(&force);
+            }
+            l.wrenches.push(w).unwrap();
+        }
+        let t = rkd.update_equation_of_motion();
+        for i in 1..6 {
+            assert_relative_eq!(t[i], expected[i], epsilon = 1.0e-9);
+        }
+
+        // a pure moment on the last link is taken by the joints above it
+        for l in rkd.links.iter_mut() {
+            l.wrenches.clear();
+        }
+        let mut w = Wrench::new();
+        w.frame = Frame::World;
+        w.moment = na::Vector3::new(0.3, -0.2, 0.1);
+        rkd.links[5].wrenches.push(w.clone()).unwrap();
+        let t = rkd.update_equation_of_motion();
+        for i in [1, 4, 5] {
+            let axis = rkd.links[i].r_quat.transform_vector(&rkd.links[i].a);
+            assert_relative_eq!(t[i], free[i] - axis.dot(&w.moment), epsilon = 1.0e-9);
+        }
+        for i in [2, 3] {
+            assert_relative_eq!(t[i], free[i], epsilon = 1.0e-9);
+        }
+
+        // forward dynamics sees the same loads
+        let ddq: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.ddqddt).collect();
+        rkd.forward_dynamics(&t, None);
+        for (l, ddq) in rkd.links.iter().zip(ddq.iter()).skip(1) {
+            assert_relative_eq!(l.ddqddt, *ddq, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn mounting() {
+        let mut rkd = tree();
+        let q: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.q).collect();
+        let tip = rkd.link_pose(5);
+
+        // a wall mount is the same as gravity turned into the base frame
+        let mount = na::Isometry3::new(
+            na::Vector3::new(1.0, 2.0, 0.5),
+            na::Vector3::new(0.0, core::f64::consts::FRAC_PI_2, 0.0),
+        );
+        let g = na::Vector3::new(0.0, 0.0, -9.81);
+        rkd.gravity = mount.rotation.inverse_transform_vector(&g);
+        let expected = rkd.gravity_torques(&q);
+
+        rkd.gravity = g;
+        rkd.base = mount;
+        rkd.update_kinematic_relationship();
+        assert_relative_eq!(
+            rkd.link_pose(5).translation.vector,
+            (mount * tip).translation.vector,
+            epsilon = 1.0e-12
+        );
+        let t = rkd.gravity_torques(&q);
+        for i in 1..6 {
+            assert_relative_eq!(t[i], expected[i], epsilon = 1.0e-9);
+        }
+
+        // hanging from the ceiling flips the gravity torques
+        let mut up = tree();
+        up.gravity = -g;
+        let expected = up.gravity_torques(&q);
+        rkd.base = na::Isometry3::rotation(na::Vector3::new(core::f64::consts::PI, 0.0, 0.0));
+        let t = rkd.gravity_torques(&q);
+        for i in 1..6 {
+            assert_relative_eq!(t[i], expected[i], epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn actuators() {
+        let mut rkd = tree();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        let ddq = [0.0, 0.4, -1.3, 0.9, 2.1, -0.6];
+        for (l, ddq) in rkd.links.iter_mut().zip(ddq.iter()) {
+            l.ddqddt = *ddq;
+        }
+        rkd.update_kinematic_relationship();
+        let q: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.q).collect();
+        let rigid = rkd.update_equation_of_motion();
+        let m_rigid = rkd.mass_matrix::<6>();
+        let g_rigid = rkd.gravity_torques(&q);
+
+        for (i, l) in rkd.links.iter_mut().enumerate().skip(1) {
+            l.actuator.gear_ratio = 50.0 + 10.0 * i as f64;
+            l.actuator.rotor_inertia = 1.0e-5;
+            l.actuator.coulomb = 0.3;
+            l.actuator.static_friction = 0.5;
+            l.actuator.stribeck_velocity = 0.2;
+            l.actuator.viscous = 0.1 * i as f64;
+        }
+        let t = rkd.update_equation_of_motion();
+        let m = rkd.mass_matrix::<6>();
+        let g = rkd.gravity_torques(&q);
+        for (i, l) in rkd.links.iter().enumerate().skip(1) {
+            let extra = l.actuator.armature() * l.ddqddt + l.actuator.friction(l.dqdt);
+            assert_relative_eq!(t[i], rigid[i] + extra, epsilon = 1.0e-9);
+            assert_relative_eq!(
+                m[(i, i)],
+                m_rigid[(i, i)] + l.actuator.armature(),
+                epsilon = 1.0e-12
+            );
+            // holding still needs no more than the rigid body
+            assert_relative_eq!(g[i], g_rigid[i], epsilon = 1.0e-12);
+        }
+
+        // forward dynamics undoes the inverse dynamics with the actuators
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.0;
+        }
+        rkd.forward_dynamics(&t, None);
+        for (l, ddq) in rkd.links.iter().zip(ddq.iter()).skip(1) {
+            assert_relative_eq!(l.ddqddt, *ddq, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn motor_torques() {
+        let mut rkd = tree();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        let ddq = [0.0, 0.4, -1.3, 0.9, 2.1, -0.6];
+        for ((i, l), ddq) in rkd.links.iter_mut().enumerate().zip(ddq.iter()) {
+            l.ddqddt = *ddq;
+            l.actuator.gear_ratio = 50.0 + 10.0 * i as f64;
+            l.actuator.rotor_inertia = 1.0e-5;
+            l.actuator.efficiency = 0.7;
+            l.actuator.viscous = 0.1;
+        }
+        rkd.update_kinematic_relationship();
+        let t = rkd.update_equation_of_motion();
+        let motor = rkd.motor_torques(&t);
+        assert_eq!(motor[0], 0.0);
+        let mut driving = false;
+        let mut braking = false;
+        for (i, l) in rkd.links.iter().enumerate().skip(1) {
+            let n = l.actuator.gear_ratio;
+            if t[i] * l.dqdt > 0.0 {
+                // the motor supplies the loss of the gear
+                assert_relative_eq!(motor[i], t[i] / (0.7 * n), epsilon = 1.0e-12);
+                driving = true;
+            } else {
+                // the joint back-drives the motor through the gear
+                assert_relative_eq!(motor[i], t[i] * 0.7 / n, epsilon = 1.0e-12);
+                braking = true;
+            }
+        }
+        assert!(driving && braking);
+
+        // forward dynamics of the motor torques gives back the accelerations
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.0;
+        }
+        let tau = rkd.joint_torques(&motor);
+        rkd.forward_dynamics(&tau, None);
+        for (l, ddq) in rkd.links.iter().zip(ddq.iter()).skip(1) {
+            assert_relative_eq!(l.ddqddt, *ddq, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn reaction_wrenches() {
+        let mut rkd = tree();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        rkd.base = na::Isometry3::new(na::Vector3::new(0.2, 0.0, 1.0), na::Vector3::x() * 0.3);
+        for l in rkd.links.iter_mut() {
+            l.ddqddt = 0.7;
+        }
+        let mut w = Wrench::new();
+        w.frame = Frame::World;
+        w.force = na::Vector3::new(1.0, -2.0, 3.0);
+        w.point = na::Vector3::new(0.1, 0.0, 0.0);
+        rkd.links[3].wrenches.push(w.clone()).unwrap();
+        rkd.update_kinematic_relationship();
+        let t = rkd.update_equation_of_motion();
+        for l in rkd.links.iter().skip(1) {
+            assert_relative_eq!(l.n_vec.dot(&l.a), t[l.id as usize], epsilon = 1.0e-12);
+        }
+
+        // the base carries the rate of momentum of the whole robot and its weight
+        let origin = rkd.links[0].p;
+        let mut force = -w.force;
+        let tip = rkd.links[3].p + rkd.links[3].r_quat.transform_vector(&w.point);
+        let mut moment = (tip - origin).cross(&-w.force);
+        for l in rkd.links.iter() {
+            let c = l.p + l.r_quat.transform_vector(&l.com) - origin;
+            let f = l.r_quat.transform_vector(&(l.ddsddt * l.mass));
+            let n = l.r_quat.transform_vector(
+                &(l.inertia_mat * l.dwdt_vec + l.w_vec.cross(&(l.inertia_mat * l.w_vec))),
+            );
+            force += f;
+            moment += c.cross(&f) + n;
+        }
+        let base = &rkd.links[0];
+        assert_relative_eq!(
+            base.r_quat.transform_vector(&base.f_vec),
+            force,
+            epsilon = 1.0e-9
+        );
+        assert_relative_eq!(
+            base.r_quat.transform_vector(&base.n_vec),
+            moment,
+            epsilon = 1.0e-9
+        );
+
+        // a sensor in the last joint of a hanging, still link reads its weight
+        for l in rkd.links.iter_mut() {
+            l.dqdt = 0.0;
+            l.ddqddt = 0.0;
+            l.wrenches.clear();
+        }
+        rkd.update_kinematic_relationship();
+        rkd.update_equation_of_motion();
+        let l = &rkd.links[5];
+        let weight = na::Vector3::new(0.0, 0.0, 9.8 * l.mass);
+        assert_relative_eq!(
+            l.r_quat.transform_vector(&l.f_vec),
+            weight,
+            epsilon = 1.0e-9
+        );
+        assert_relative_eq!(
+            l.n_vec,
+            l.com.cross(&l.r_quat.inverse_transform_vector(&weight)),
+            epsilon = 1.0e-9
+        );
+    }
+
+    #[test]
+    fn centroidal() {
+        let mut rkd = tree();
+        rkd.base = na::Isometry3::new(na::Vector3::new(0.5, -0.2, 0.3), na::Vector3::z() * 0.4);
+        rkd.update_kinematic_relationship();
+        let q: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.q).collect();
+        let dq: heapless::Vec<f64, 256> = rkd.links.iter().map(|l| l.dqdt).collect();
+        assert_relative_eq!(rkd.total_mass(), 13.5, epsilon = 1.0e-12);
+
+        // the COM Jacobian is the derivative of the COM
+        let jac = rkd.com_jacobian::<6>();
+        let h = 1.0e-6;
+        for k in 1..6 {
+            let mut com = na::Vector3::zeros();
+            for sign in [1.0, -1.0] {
+                rkd.links[k].q = q[k] + sign * h;
+                rkd.update_kinematic_relationship();
+                com += rkd.com() * (sign / (2.0 * h));
+            }
+            rkd.links[k].q = q[k];
+            assert_relative_eq!(jac.column(k).into_owned(), com, epsilon = 1.0e-6);
+        }
+        rkd.update_kinematic_relationship();
+
+        // A dq is the momentum summed over the links
+        let cmm = rkd.centroidal_momentum_matrix::<6>();
+        let momentum = cmm * na::SVector::<f64, 6>::from_column_slice(&dq);
+        let linear = rkd.linear_momentum();
+        let angular = rkd.angular_momentum();
+        assert_relative_eq!(
+            momentum.fixed_rows::<3>(0).into_owned(),
+            linear,
+            epsilon = 1.0e-12
+        );
+        assert_relative_eq!(
+            momentum.fixed_rows::<3>(3).into_owned(),
+            angular,
+            epsilon = 1.0e-12
+        );
+        assert_relative_eq!(
+            linear,
+            jac * na::SVector::<f64, 6>::from_column_slice(&dq) * rkd.total_mass(),
+            epsilon = 1.0e-12
+        );
+        for i in 0..6 {
+            assert_eq!(cmm[(i, 0)], 0.0);
+        }
+
+        // a single spinning link about its center of mass
+        let mut rkd = Rkd::<f64>::new();
+        rkd.links.push(Link::new()).unwrap();
+        let mut l1 = Link::new();
+        l1.id = 1;
+        l1.a = na::Vector3::z_axis();
+        l1.mass = 2.0;
+        l1.inertia_mat = na::Matrix3::from_diagonal(&na::Vector3::new(0.1, 0.2, 0.3));
+        l1.dqdt = 3.0;
+        rkd.links.push(l1).unwrap();
+        rkd.links[0].children.push(1).unwrap();
+        rkd.update_kinematic_relationship();
+        assert_relative_eq!(rkd.linear_momentum(), na::Vector3::zeros());
+        assert_relative_eq!(
+            rkd.angular_momentum(),
+            na::Vector3::new(0.0, 0.0, 0.9),
+            epsilon = 1.0e-12
+        );
+    }
+
     #[test]
     fn lane2d() {
         let l_1 = 2.0;
@@ -204,8 +1247,8 @@ mod test_rkd {
         let mut rkd = Rkd::<f32>::new();
         let mut l0 = Link::new();
         l0.parent = 0;
-        l0.ddpddt = na::Vector3::new(0.0, g, 0.0);
         rkd.links.push(l0).unwrap();
+        rkd.gravity = na::Vector3::new(0.0, -g, 0.0);
         let mut l1 = Link::new();
         l1.parent = 0;
         l1.a = na::Vector3::z_axis();
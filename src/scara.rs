@@ -0,0 +1,159 @@
+use crate::ik::{wrap_angle, IkError};
+use crate::planar::Planar2R;
+
+/// Side of the elbow seen from above, looking from the base towards the tool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Handedness {
+    /// Elbow on the left, `q[1]` negative.
+    Lefty,
+    /// Elbow on the right, `q[1]` positive.
+    Righty,
+}
+
+/// Kinematics of a SCARA arm: two revolute joints about +z, a prismatic
+/// joint along +z and a roll about +z.
+///
+/// The pose of the tool is `(x, y, z, phi)`, with
+/// - `(x, y)` given by the planar arm of `l1` and `l2`,
+/// - `z = height + q[2]`,
+/// - `phi = q[0] + q[1] + q[3]`.
+pub struct Scara<T> {
+    pub l1: T,
+    pub l2: T,
+    pub height: T,
+    pub handedness: Handedness,
+}
+
+impl<T: na::RealField> Default for Scara<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Scara<T> {
+    pub fn new() -> Self {
+        Self {
+            l1: T::zero(),
+            l2: T::zero(),
+            height: T::zero(),
+            handedness: Handedness::Righty,
+        }
+    }
+
+    fn arm(&self) -> Planar2R<T> {
+        Planar2R {
+            l1: self.l1.clone(),
+            l2: self.l2.clone(),
+        }
+    }
+
+    pub fn forward(&self, q: &[T; 4]) -> na::Vector4<T> {
+        let p = self.arm().forward(&[q[0].clone(), q[1].clone()]);
+        na::Vector4::new(
+            p.x.clone(),
+            p.y.clone(),
+            self.height.clone() + q[2].clone(),
+            q[0].clone() + q[1].clone() + q[3].clone(),
+        )
+    }
+
+    /// Jacobian of `(x, y, z, phi)` with respect to `q`.
+    pub fn jacobian(&self, q: &[T; 4]) -> na::Matrix4<T> {
+        let arm = self.arm().jacobian(&[q[0].clone(), q[1].clone()]);
+        let mut jac = na::Matrix4::zeros();
+        jac.fixed_slice_mut::<2, 2>(0, 0).copy_from(&arm);
+        jac[(2, 2)] = T::one();
+        jac[(3, 0)] = T::one();
+        jac[(3, 1)] = T::one();
+        jac[(3, 3)] = T::one();
+        jac
+    }
+
+    pub fn is_reachable(&self, pose: &na::Vector4<T>) -> bool {
+        self.arm().is_reachable(&pose.xy())
+    }
+
+    /// Joint values reaching `pose` with the elbow on the side of `handedness`.
+    pub fn solve(&self, pose: &na::Vector4<T>) -> Result<[T; 4], IkError> {
+        let branches = self.arm().solve(&pose.xy())?;
+        let [q0, q1] = match self.handedness {
+            Handedness::Righty => branches[0].clone(),
+            Handedness::Lefty => branches[1].clone(),
+        };
+        let q3 = wrap_angle(pose.w.clone() - q0.clone() - q1.clone());
+        Ok([q0, q1, pose.z.clone() - self.height.clone(), q3])
+    }
+}
+
+#[cfg(test)]
+mod test_scara {
+    use crate::ik::{wrap_angle, IkError};
+    use crate::scara::{Handedness, Scara};
+    use approx::assert_relative_eq;
+
+    fn scara() -> Scara<f64> {
+        let mut scara = Scara::new();
+        scara.l1 = 0.35;
+        scara.l2 = 0.3;
+        scara.height = 0.4;
+        scara
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut scara = scara();
+        for q in [
+            [0.3, 1.2, -0.1, 0.5],
+            [-1.0, -0.8, -0.2, 2.0],
+            [2.5, 0.4, 0.0, -3.0],
+        ] {
+            scara.handedness = if q[1] >= 0.0 {
+                Handedness::Righty
+            } else {
+                Handedness::Lefty
+            };
+            let pose = scara.forward(&q);
+            let ans = scara.solve(&pose).unwrap();
+            for i in 0..4 {
+                assert_relative_eq!(wrap_angle(ans[i] - q[i]), 0.0, epsilon = 1.0e-9);
+            }
+
+            // the other arm reaches the same pose
+            scara.handedness = match scara.handedness {
+                Handedness::Righty => Handedness::Lefty,
+                Handedness::Lefty => Handedness::Righty,
+            };
+            let other = scara.forward(&scara.solve(&pose).unwrap());
+            assert_relative_eq!(other.xyz(), pose.xyz(), epsilon = 1.0e-9);
+            assert_relative_eq!(wrap_angle(other.w - pose.w), 0.0, epsilon = 1.0e-9);
+        }
+    }
+
+    #[test]
+    fn unreachable() {
+        let scara = scara();
+        let pose = na::Vector4::new(0.7, 0.0, 0.3, 0.0);
+        assert!(!scara.is_reachable(&pose));
+        assert_eq!(scara.solve(&pose), Err(IkError::TooFar));
+        assert_eq!(
+            scara.solve(&na::Vector4::new(0.01, 0.0, 0.3, 0.0)),
+            Err(IkError::TooNear)
+        );
+    }
+
+    #[test]
+    fn jacobian() {
+        let scara = scara();
+        let q = [0.3, 1.2, -0.1, 0.5];
+        let jac = scara.jacobian(&q);
+        let h = 1.0e-6;
+        for k in 0..4 {
+            let mut plus = q;
+            let mut minus = q;
+            plus[k] += h;
+            minus[k] -= h;
+            let diff = (scara.forward(&plus) - scara.forward(&minus)) / (2.0 * h);
+            assert_relative_eq!(jac.column(k).into_owned(), diff, epsilon = 1.0e-6);
+        }
+    }
+}
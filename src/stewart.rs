@@ -0,0 +1,246 @@
+use crate::ik::IkError;
+
+/// Kinematics of a 6-6 Stewart–Gough platform.
+///
+/// Leg `i` joins `base[i]`, in the base frame, to `platform[i]`, in the
+/// platform frame. Poses are those of the platform frame in the base frame.
+/// `forward` is solved by Newton's method, stopping once every leg length
+/// is within `tolerance` or after `max_iterations`.
+pub struct Stewart<T> {
+    pub base: [na::Vector3<T>; 6],
+    pub platform: [na::Vector3<T>; 6],
+    pub max_iterations: usize,
+    pub tolerance: T,
+}
+
+impl<T: na::RealField> Default for Stewart<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Stewart<T> {
+    pub fn new() -> Self {
+        Self {
+            base: [
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+            ],
+            platform: [
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+                na::Vector3::zeros(),
+            ],
+            max_iterations: 20,
+            tolerance: T::default_epsilon().sqrt(),
+        }
+    }
+
+    /// Anchors in pairs on two circles in the xy planes of their frames.
+    ///
+    /// The base pairs are centered at the azimuths 0°, 120° and 240°, and
+    /// the platform pairs at 60°, 180° and 300°, the two anchors of a pair
+    /// being `gap` apart. Each base anchor is joined to the nearest anchor
+    /// of the neighbouring platform pair.
+    pub fn from_radii(base_radius: T, platform_radius: T, base_gap: T, platform_gap: T) -> Self {
+        let mut stewart = Self::new();
+        let two = T::one() + T::one();
+        let third = T::two_pi() / na::convert(3.0);
+        let anchor = |radius: &T, azimuth: T| {
+            let (s, c) = azimuth.sin_cos();
+            na::Vector3::new(radius.clone() * c, radius.clone() * s, T::zero())
+        };
+        for k in 0..3 {
+            let center = third.clone() * na::convert::<f64, T>(k as f64);
+            let half_base = base_gap.clone() / two.clone();
+            let half_platform = platform_gap.clone() / two.clone();
+            let half_third = third.clone() / two.clone();
+            stewart.base[2 * k] = anchor(&base_radius, center.clone() - half_base.clone());
+            stewart.base[2 * k + 1] = anchor(&base_radius, center.clone() + half_base);
+            stewart.platform[2 * k] = anchor(
+                &platform_radius,
+                center.clone() - half_third.clone() + half_platform.clone(),
+            );
+            stewart.platform[2 * k + 1] =
+                anchor(&platform_radius, center + half_third - half_platform);
+        }
+        stewart
+    }
+
+    /// Leg vectors from the base anchors to the platform anchors.
+    pub fn legs(&self, pose: &na::Isometry3<T>) -> [na::Vector3<T>; 6] {
+        let leg = |i: usize| {
+            pose.transform_point(&self.platform[i].clone().into())
+                .coords
+                - self.base[i].clone()
+        };
+        [leg(0), leg(1), leg(2), leg(3), leg(4), leg(5)]
+    }
+
+    /// Leg lengths holding the platform at `pose`.
+    pub fn solve(&self, pose: &na::Isometry3<T>) -> [T; 6] {
+        let legs = self.legs(pose);
+        [
+            legs[0].norm(),
+            legs[1].norm(),
+            legs[2].norm(),
+            legs[3].norm(),
+            legs[4].norm(),
+            legs[5].norm(),
+        ]
+    }
+
+    /// Jacobian of the leg lengths with respect to the platform twist
+    /// `(v, ω)`, both in the base frame with `v` the velocity of the platform origin.
+    ///
+    /// Row `i` is `[n_i, R a_i × n_i]` with `n_i` the unit vector of leg `i`,
+    /// so `Jᵀ f` is the wrench on the platform of the leg forces `f`.
+    pub fn jacobian(&self, pose: &na::Isometry3<T>) -> na::Matrix6<T> {
+        let legs = self.legs(pose);
+        let mut jac = na::Matrix6::zeros();
+        for i in 0..6 {
+            let n = legs[i].normalize();
+            let ra = pose.rotation.transform_vector(&self.platform[i]);
+            let m = ra.cross(&n);
+            for k in 0..3 {
+                jac[(i, k)] = n[k].clone();
+                jac[(i, k + 3)] = m[k].clone();
+            }
+        }
+        jac
+    }
+
+    /// Wrench `(f, n)` on the platform, the moment about its origin, from
+    /// the leg forces `forces`.
+    pub fn wrench(&self, pose: &na::Isometry3<T>, forces: &na::Vector6<T>) -> na::Vector6<T> {
+        self.jacobian(pose).transpose() * forces
+    }
+
+    /// Platform pose with the leg lengths `lengths`, searched from `initial`.
+    pub fn forward(
+        &self,
+        lengths: &[T; 6],
+        initial: &na::Isometry3<T>,
+    ) -> Result<na::Isometry3<T>, IkError> {
+        let mut pose = initial.clone();
+        for _ in 0..=self.max_iterations {
+            let current = self.solve(&pose);
+            let mut err = na::Vector6::zeros();
+            for i in 0..6 {
+                err[i] = current[i].clone() - lengths[i].clone();
+            }
+            if err.amax() <= self.tolerance {
+                return Ok(pose);
+            }
+            let step = self
+                .jacobian(&pose)
+                .lu()
+                .solve(&(-err))
+                .ok_or(IkError::NotConverged)?;
+            let v = na::Vector3::new(step[0].clone(), step[1].clone(), step[2].clone());
+            let w = na::Vector3::new(step[3].clone(), step[4].clone(), step[5].clone());
+            pose.translation.vector += v;
+            pose.rotation = na::UnitQuaternion::from_scaled_axis(w) * pose.rotation.clone();
+        }
+        Err(IkError::NotConverged)
+    }
+}
+
+#[cfg(test)]
+mod test_stewart {
+    use crate::ik::IkError;
+    use crate::stewart::Stewart;
+    use approx::assert_relative_eq;
+
+    fn stewart() -> Stewart<f64> {
+        Stewart::from_radii(0.5, 0.3, 0.3, 0.2)
+    }
+
+    fn pose() -> na::Isometry3<f64> {
+        na::Isometry3::new(
+            na::Vector3::new(0.03, -0.02, 0.62),
+            na::Vector3::new(0.05, -0.08, 0.1),
+        )
+    }
+
+    #[test]
+    fn symmetric_home() {
+        let stewart = stewart();
+        let lengths = stewart.solve(&na::Isometry3::translation(0.0, 0.0, 0.6));
+        for l in lengths.iter() {
+            assert_relative_eq!(*l, lengths[0], epsilon = 1.0e-12);
+        }
+        assert!(lengths[0] > 0.6);
+    }
+
+    #[test]
+    fn round_trip() {
+        let stewart = stewart();
+        let target = pose();
+        let lengths = stewart.solve(&target);
+        let pose = stewart
+            .forward(&lengths, &na::Isometry3::translation(0.0, 0.0, 0.6))
+            .unwrap();
+        assert_relative_eq!(
+            pose.translation.vector,
+            target.translation.vector,
+            epsilon = 1.0e-6
+        );
+        assert_relative_eq!(
+            pose.rotation.angle_to(&target.rotation),
+            0.0,
+            epsilon = 1.0e-6
+        );
+
+        let mut stewart = stewart;
+        stewart.max_iterations = 0;
+        assert_eq!(
+            stewart.forward(&lengths, &na::Isometry3::translation(0.0, 0.0, 0.6)),
+            Err(IkError::NotConverged)
+        );
+    }
+
+    #[test]
+    fn jacobian() {
+        let stewart = stewart();
+        let pose = pose();
+        let jac = stewart.jacobian(&pose);
+        let h = 1.0e-6;
+        for k in 0..6 {
+            let mut twist = na::Vector6::zeros();
+            twist[k] = h;
+            let step = |sign: f64| {
+                let mut p = pose;
+                p.translation.vector += twist.fixed_rows::<3>(0) * sign;
+                p.rotation = na::UnitQuaternion::from_scaled_axis(twist.fixed_rows::<3>(3) * sign)
+                    * p.rotation;
+                stewart.solve(&p)
+            };
+            let plus = step(1.0);
+            let minus = step(-1.0);
+            for i in 0..6 {
+                assert_relative_eq!(
+                    jac[(i, k)],
+                    (plus[i] - minus[i]) / (2.0 * h),
+                    epsilon = 1.0e-6
+                );
+            }
+        }
+
+        // virtual work: f·dl = w·twist
+        let forces = na::Vector6::new(10.0, -20.0, 30.0, 5.0, -5.0, 15.0);
+        let twist = na::Vector6::new(0.01, -0.02, 0.03, 0.002, 0.001, -0.003);
+        assert_relative_eq!(
+            stewart.wrench(&pose, &forces).dot(&twist),
+            forces.dot(&(jac * twist)),
+            epsilon = 1.0e-9
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use heapless::Vec;
+
+use crate::rkd::Rkd;
+
+/// Ground plane through `point` with the upward unit `normal`.
+///
+/// Points on the plane have the 2D coordinates `(u, v)` along two in-plane
+/// axes, which are x and y for the default plane `z = 0`.
+#[derive(Debug, Clone)]
+pub struct Plane<T> {
+    pub point: na::Vector3<T>,
+    pub normal: na::Unit<na::Vector3<T>>,
+}
+
+impl<T: na::RealField> Default for Plane<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: na::RealField> Plane<T> {
+    pub fn new() -> Self {
+        Self {
+            point: na::Vector3::zeros(),
+            normal: na::Vector3::z_axis(),
+        }
+    }
+
+    /// In-plane axes `u` and `v`, right-handed with `normal`.
+    pub fn axes(&self) -> (na::Vector3<T>, na::Vector3<T>) {
+        let n = self.normal.clone().into_inner();
+        let e = if n.x.clone().abs() < na::convert(0.9) {
+            na::Vector3::x()
+        } else {
+            na::Vector3::y()
+        };
+        let v = n.cross(&e).normalize();
+        let u = v.cross(&n);
+        (u, v)
+    }
+
+    /// Plane coordinates of `p` projected along `normal`.
+    pub fn project(&self, p: &na::Vector3<T>) -> na::Vector2<T> {
+        let (u, v) = self.axes();
+        let d = p.clone() - self.point.clone();
+        na::Vector2::new(d.dot(&u), d.dot(&v))
+    }
+
+    /// Point on the plane at the plane coordinates `uv`.
+    pub fn lift(&self, uv: &na::Vector2<T>) -> na::Vector3<T> {
+        let (u, v) = self.axes();
+        self.point.clone() + u * uv.x.clone() + v * uv.y.clone()
+    }
+
+    /// Point on the plane where a wrench with the force `force` and the moment
+    /// `moment` about `point` has no moment along the plane, or `None` if the
+    /// force does not push into the plane.
+    fn center_of_pressure(
+        &self,
+        force: &na::Vector3<T>,
+        moment: &na::Vector3<T>,
+    ) -> Option<na::Vector3<T>> {
+        let normal = self.normal.dot(force);
+        if normal <= T::zero() {
+            return None;
+        }
+        Some(self.point.clone() + self.normal.cross(moment) / normal)
+    }
+}
+
+/// Zero moment point of `rkd` on `plane`, in world coordinate.
+///
+/// The ground is taken to carry the weight and the rate of momentum of all
+/// the links, less the `wrenches` on them, at the accelerations of the last
+/// `update_kinematic_relationship`. `None` if the robot would have to be
+/// pulled down to the ground.
+pub fn zmp<T: na::RealField>(rkd: &Rkd<T>, plane: &Plane<T>) -> Option<na::Vector3<T>> {
+    let mut force = na::Vector3::zeros();
+    let mut moment = na::Vector3::zeros();
+    for l in rkd.links.iter() {
+        // ddsddt holds gravity as an upward acceleration of the root
+        let f = l
+            .r_quat
+            .transform_vector(&(l.ddsddt.clone() * l.mass.clone()));
+        let n = l.r_quat.transform_vector(
+            &(l.inertia_mat.clone() * l.dwdt_vec.clone()
+                + l.w_vec.cross(&(l.inertia_mat.clone() * l.w_vec.clone()))),
+        );
+        let c = l.p.clone() + l.r_quat.transform_vector(&l.com) - plane.point.clone();
+        moment += c.cross(&f) + n;
+        force += f;
+
+        let (f_ext, n_ext) = l.external_wrench();
+        let f_ext = l.r_quat.transform_vector(&f_ext);
+        let n_ext = l.r_quat.transform_vector(&n_ext);
+        moment -= n_ext + (l.p.clone() - plane.point.clone()).cross(&f_ext);
+        force -= f_ext;
+    }
+    plane.center_of_pressure(&force, &moment)
+}
+
+/// Center of pressure on `plane` of the contact forces `(point, force)`,
+/// both in world coordinate, or `None` if they do not push into the plane.
+pub fn cop<T: na::RealField>(
+    contacts: &[(na::Vector3<T>, na::Vector3<T>)],
+    plane: &Plane<T>,
+) -> Option<na::Vector3<T>> {
+    let mut force = na::Vector3::zeros();
+    let mut moment = na::Vector3::zeros();
+    for (p, f) in contacts.iter() {
+        moment += (p.clone() - plane.point.clone()).cross(f);
+        force += f.clone();
+    }
+    plane.center_of_pressure(&force, &moment)
+}
+
+/// Convex hull of the contact points in plane coordinates, counterclockwise.
+///
+/// It is made of at most 64 finite points. Built only by `from_points` and
+/// `from_contacts`, so it always has a vertex.
+#[derive(Debug, Clone)]
+pub struct SupportPolygon<T> {
+    vertices: Vec<na::Vector2<T>, 64>,
+}
+
+impl<T: na::RealField> SupportPolygon<T> {
+    /// Convex hull of `points` by the monotone chain.
+    ///
+    /// Points with a NaN or infinite coordinate are skipped. `None` if no
+    /// point is left or more than 64 are.
+    pub fn from_points(points: &[na::Vector2<T>]) -> Option<Self> {
+        let mut sorted = Vec::<na::Vector2<T>, 64>::new();
+        for p in points.iter().filter(|p| p.iter().all(|x| x.is_finite())) {
+            sorted.push(p.clone()).ok()?;
+        }
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_unstable_by(|a, b| {
+            (a.x.clone(), a.y.clone())
+                .partial_cmp(&(b.x.clone(), b.y.clone()))
+                .unwrap()
+        });
+        let turn = |o: &na::Vector2<T>, a: &na::Vector2<T>, b: &na::Vector2<T>| {
+            (a.clone() - o.clone()).perp(&(b.clone() - o.clone()))
+        };
+
+        let mut hull = Vec::<na::Vector2<T>, 128>::new();
+        for pass in 0..2 {
+            let start = hull.len();
+            let mut chain = |p: &na::Vector2<T>| {
+                while hull.len() >= start + 2
+                    && turn(&hull[hull.len() - 2], &hull[hull.len() - 1], p) <= T::zero()
+                {
+                    hull.pop();
+                }
+                hull.push(p.clone()).unwrap();
+            };
+            if pass == 0 {
+                sorted.iter().for_each(&mut chain);
+            } else {
+                sorted.iter().rev().for_each(&mut chain);
+            }
+            // the last point of a chain is the first of the other
+            hull.pop();
+        }
+        let mut vertices = Vec::new();
+        for v in hull.iter() {
+            if vertices.last() != Some(v) {
+                vertices.push(v.clone()).unwrap();
+            }
+        }
+        if vertices.is_empty() {
+            vertices.push(sorted[0].clone()).unwrap();
+        }
+        Some(Self { vertices })
+    }
+
+    /// Convex hull of the contact points `points` in world coordinate,
+    /// projected on `plane`, as `from_points`.
+    pub fn from_contacts(points: &[na::Vector3<T>], plane: &Plane<T>) -> Option<Self> {
+        let mut projected = Vec::<na::Vector2<T>, 64>::new();
+        for p in points.iter().filter(|p| p.iter().all(|x| x.is_finite())) {
+            projected.push(plane.project(p)).ok()?;
+        }
+        Self::from_points(&projected)
+    }
+
+    /// Vertices of the hull, counterclockwise.
+    pub fn vertices(&self) -> &[na::Vector2<T>] {
+        &self.vertices
+    }
+
+    pub fn contains(&self, p: &na::Vector2<T>) -> bool {
+        self.margin(p) >= T::zero()
+    }
+
+    /// Distance from `p` to the edge of the polygon, positive inside and
+    /// negative outside. A polygon of one or two vertices has no inside.
+    pub fn margin(&self, p: &na::Vector2<T>) -> T {
+        let n = self.vertices.len();
+        if n == 1 {
+            return -(p.clone() - self.vertices[0].clone()).norm();
+        }
+        let mut inside = n >= 3;
+        let mut to_line = None::<T>;
+        let mut to_edge = None::<T>;
+        for i in 0..n {
+            let a = &self.vertices[i];
+            let b = &self.vertices[(i + 1) % n];
+            let edge = b.clone() - a.clone();
+            let d = p.clone() - a.clone();
+            let side = edge.perp(&d) / edge.norm();
+            inside &= side >= T::zero();
+            let t = (d.dot(&edge) / edge.norm_squared()).clamp(T::zero(), T::one());
+            let dist = (d - edge * t).norm();
+            to_line = Some(to_line.map_or(side.clone(), |m| m.min(side)));
+            to_edge = Some(to_edge.map_or(dist.clone(), |m| m.min(dist)));
+        }
+        if inside {
+            to_line.unwrap()
+        } else {
+            -to_edge.unwrap()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_zmp {
+    use crate::link::{Frame, Link, Wrench};
+    use crate::rkd::Rkd;
+    use crate::zmp::{cop, zmp, Plane, SupportPolygon};
+    use approx::assert_relative_eq;
+
+    /// Two-link arm standing on a foot at the root.
+    fn robot() -> Rkd<f64> {
+        let mut rkd = Rkd::new();
+        let mut l0 = Link::new();
+        l0.mass = 3.0;
+        l0.com = na::Vector3::new(0.02, 0.0, 0.05);
+        l0.children.push(1).unwrap();
+        rkd.links.push(l0).unwrap();
+        let mut l1 = Link::new();
+        l1.id = 1;
+        l1.a = na::Vector3::y_axis();
+        l1.b = na::Vector3::new(0.0, 0.0, 0.1);
+        l1.mass = 2.0;
+        l1.com = na::Vector3::new(0.0, 0.01, 0.3);
+        l1.inertia_mat = na::Matrix3::from_diagonal(&na::Vector3::new(0.02, 0.02, 0.005));
+        l1.q = 0.3;
+        l1.children.push(2).unwrap();
+        rkd.links.push(l1).unwrap();
+        let mut l2 = Link::new();
+        l2.id = 2;
+        l2.parent = 1;
+        l2.a = na::Unit::new_normalize(na::Vector3::new(1.0, 1.0, 0.0));
+        l2.b = na::Vector3::new(0.0, 0.0, 0.6);
+        l2.mass = 1.5;
+        l2.com = na::Vector3::new(0.1, 0.0, 0.2);
+        l2.inertia_mat = na::Matrix3::from_diagonal(&na::Vector3::new(0.01, 0.015, 0.004));
+        l2.q = -0.8;
+        rkd.links.push(l2).unwrap();
+        rkd.gravity = na::Vector3::new(0.0, 0.0, -9.8);
+        rkd.update_kinematic_relationship();
+        rkd
+    }
+
+    #[test]
+    fn static_zmp() {
+        // at rest the ZMP is below the center of mass
+        let rkd = robot();
+        let plane = Plane::new();
+        let p = zmp(&rkd, &plane).unwrap();
+        let com = rkd.com();
+        assert_relative_eq!(p, na::Vector3::new(com.x, com.y, 0.0), epsilon = 1.0e-12);
+
+        // on a slope with gravity along its normal
+        let mut rkd = rkd;
+        let mut plane = Plane::new();
+        plane.normal = na::Unit::new_normalize(na::Vector3::new(0.3, -0.2, 1.0));
+        plane.point = na::Vector3::new(0.1, 0.2, -0.05);
+        rkd.gravity = plane.normal.into_inner() * -9.8;
+        rkd.update_kinematic_relationship();
+        let p = zmp(&rkd, &plane).unwrap();
+        assert_relative_eq!(p, plane.lift(&plane.project(&com)), epsilon = 1.0e-12);
+
+        // nothing holds the robot up without gravity
+        rkd.gravity = na::Vector3::zeros();
+        rkd.update_kinematic_relationship();
+        assert!(zmp(&rkd, &plane).is_none());
+    }
+
+    #[test]
+    fn dynamic_zmp() {
+        // the ZMP is where the base reaction has no moment along the ground
+        let mut rkd = robot();
+        rkd.links[1].dqdt = 1.2;
+        rkd.links[2].dqdt = -0.7;
+        rkd.links[1].ddqddt = 3.0;
+        rkd.links[2].ddqddt = 1.5;
+        let mut w = Wrench::new();
+        w.frame = Frame::World;
+        w.force = na::Vector3::new(5.0, 2.0, -1.0);
+        w.point = na::Vector3::new(0.0, 0.0, 0.3);
+        rkd.links[2].wrenches.push(w).unwrap();
+        rkd.update_kinematic_relationship();
+        rkd.update_equation_of_motion();
+
+        let plane = Plane::new();
+        let p = zmp(&rkd, &plane).unwrap();
+        let base = &rkd.links[0];
+        let force = base.r_quat.transform_vector(&base.f_vec);
+        let moment = base.r_quat.transform_vector(&base.n_vec) + base.p.cross(&force);
+        let moment_at_p = moment - p.cross(&force);
+        assert_relative_eq!(moment_at_p.x, 0.0, epsilon = 1.0e-9);
+        assert_relative_eq!(moment_at_p.y, 0.0, epsilon = 1.0e-9);
+        assert_relative_eq!(p.z, 0.0);
+
+        // the same through the contact forces of that reaction at two points
+        let a = na::Vector3::new(p.x - 0.1, p.y, 0.0);
+        let b = na::Vector3::new(p.x + 0.1, p.y, 0.0);
+        let half = force * 0.5;
+        let contacts = [(a, half), (b, half)];
+        assert_relative_eq!(cop(&contacts, &plane).unwrap(), p, epsilon = 1.0e-12);
+    }
+
+    #[test]
+    fn cop_weights() {
+        let plane = Plane::new();
+        let contacts = [
+            (
+                na::Vector3::new(0.0, 0.0, 0.0),
+                na::Vector3::new(0.0, 0.0, 30.0),
+            ),
+            (
+                na::Vector3::new(0.4, 0.2, 0.0),
+                na::Vector3::new(0.0, 0.0, 10.0),
+            ),
+        ];
+        assert_relative_eq!(
+            cop(&contacts, &plane).unwrap(),
+            na::Vector3::new(0.1, 0.05, 0.0),
+            epsilon = 1.0e-12
+        );
+        let pulling = [(na::Vector3::zeros(), na::Vector3::new(0.0, 0.0, -1.0))];
+        assert!(cop(&pulling, &plane).is_none());
+    }
+
+    #[test]
+    fn support_polygon() {
+        // two feet, with points inside and on the edges dropped
+        let points = [
+            na::Vector2::new(0.0, 0.0),
+            na::Vector2::new(0.2, 0.0),
+            na::Vector2::new(0.2, 0.1),
+            na::Vector2::new(0.0, 0.1),
+            na::Vector2::new(0.1, 0.05),
+            na::Vector2::new(0.0, 0.3),
+            na::Vector2::new(0.2, 0.3),
+            na::Vector2::new(0.2, 0.4),
+            na::Vector2::new(0.0, 0.4),
+            na::Vector2::new(0.0, 0.2),
+        ];
+        let poly = SupportPolygon::from_points(&points).unwrap();
+        assert_eq!(
+            poly.vertices(),
+            &[
+                na::Vector2::new(0.0, 0.0),
+                na::Vector2::new(0.2, 0.0),
+                na::Vector2::new(0.2, 0.4),
+                na::Vector2::new(0.0, 0.4),
+            ]
+        );
+        assert!(poly.contains(&na::Vector2::new(0.1, 0.2)));
+        assert_relative_eq!(poly.margin(&na::Vector2::new(0.05, 0.2)), 0.05);
+        assert!(!poly.contains(&na::Vector2::new(0.3, 0.2)));
+        assert_relative_eq!(poly.margin(&na::Vector2::new(0.3, 0.2)), -0.1);
+        assert_relative_eq!(
+            poly.margin(&na::Vector2::new(0.5, 0.8)),
+            -0.5,
+            epsilon = 1.0e-12
+        );
+
+        // points that are not finite are skipped, too many give none
+        let mut with_nan = points;
+        with_nan[4] = na::Vector2::new(f64::NAN, 0.05);
+        with_nan[9] = na::Vector2::new(0.0, f64::INFINITY);
+        let poly = SupportPolygon::from_points(&with_nan).unwrap();
+        assert_eq!(poly.vertices().len(), 4);
+        assert!(SupportPolygon::from_points(&[na::Vector2::new(f64::NAN, 0.0)]).is_none());
+        let ring: heapless::Vec<na::Vector2<f64>, 100> = (0..100)
+            .map(|i| {
+                let a = i as f64 * 0.0628;
+                na::Vector2::new(a.cos(), a.sin())
+            })
+            .collect();
+        assert!(SupportPolygon::from_points(&ring[..65]).is_none());
+        assert!(SupportPolygon::from_points(&ring).is_none());
+        assert_eq!(
+            SupportPolygon::from_points(&ring[..64])
+                .unwrap()
+                .vertices()
+                .len(),
+            64
+        );
+        let plane = Plane::new();
+        let contacts: heapless::Vec<na::Vector3<f64>, 100> = ring
+            .iter()
+            .map(|p| na::Vector3::new(p.x, p.y, 0.0))
+            .collect();
+        assert!(SupportPolygon::from_contacts(&contacts, &plane).is_none());
+
+        // degenerate hulls of a point and of a segment
+        let poly = SupportPolygon::from_points(&[na::Vector2::new(1.0, 1.0); 3]).unwrap();
+        assert_eq!(poly.vertices().len(), 1);
+        assert_relative_eq!(poly.margin(&na::Vector2::new(1.0, 2.0)), -1.0);
+        let poly = SupportPolygon::from_points(&[
+            na::Vector2::new(0.0, 0.0),
+            na::Vector2::new(1.0, 0.0),
+            na::Vector2::new(0.5, 0.0),
+        ])
+        .unwrap();
+        assert_eq!(poly.vertices().len(), 2);
+        assert!(poly.contains(&na::Vector2::new(0.5, 0.0)));
+        assert_relative_eq!(poly.margin(&na::Vector2::new(0.5, -0.2)), -0.2);
+        assert!(SupportPolygon::<f64>::from_points(&[]).is_none());
+    }
+
+    #[test]
+    fn plane_coordinates() {
+        let mut plane = Plane::new();
+        plane.normal = na::Unit::new_normalize(na::Vector3::new(1.0, 0.5, 0.2));
+        plane.point = na::Vector3::new(0.3, -0.1, 0.2);
+        let (u, v) = plane.axes();
+        assert_relative_eq!(u.cross(&v), plane.normal.into_inner(), epsilon = 1.0e-12);
+        let uv = na::Vector2::new(0.4, -0.7);
+        assert_relative_eq!(plane.project(&plane.lift(&uv)), uv, epsilon = 1.0e-12);
+
+        // a slanted foot seen on its own plane
+        let corners = [
+            plane.lift(&na::Vector2::new(0.0, 0.0)),
+            plane.lift(&na::Vector2::new(0.2, 0.0)) + plane.normal.into_inner() * 0.01,
+            plane.lift(&na::Vector2::new(0.2, 0.1)),
+            plane.lift(&na::Vector2::new(0.0, 0.1)),
+        ];
+        let poly = SupportPolygon::from_contacts(&corners, &plane).unwrap();
+        assert_eq!(poly.vertices().len(), 4);
+        assert_relative_eq!(
+            poly.margin(&na::Vector2::new(0.1, 0.05)),
+            0.05,
+            epsilon = 1.0e-12
+        );
+    }
+}